@@ -1,70 +1,738 @@
-use std::collections::HashMap;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
+use std::iter::Peekable;
+use std::marker::PhantomData;
+use std::ops::{Add, Neg, Sub};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 fn main() {
     let input = include_str!("input.txt");
 
-    println!("Part 1: {}", part1(input));
-    println!("Part 2: {}", part2(input));
+    if std::env::args().nth(1).as_deref() == Some("reconcile") {
+        let path = std::env::args()
+            .nth(2)
+            .expect("Usage: day01 reconcile <path> [largest] [usize|i64|u128|decimal]");
+        let largest = std::env::args()
+            .nth(3)
+            .map_or(10, |largest| largest.parse().unwrap());
+
+        match std::env::args().nth(4).as_deref() {
+            None | Some("usize") => reconcile::<usize>(&path, largest),
+            Some("i64") => reconcile::<i64>(&path, largest),
+            Some("u128") => reconcile::<u128>(&path, largest),
+            Some("decimal") => reconcile::<Decimal>(&path, largest),
+            Some(ids) => panic!("Unknown ID type: {}", ids),
+        }
+
+        return;
+    }
+
+    match std::env::args().nth(1).as_deref() {
+        None | Some("usize") => {
+            println!("Part 1: {}", part1(input));
+            println!("Part 2: {}", part2(input));
+        }
+        Some("i64") => print_parts::<i64>(input),
+        Some("u128") => print_parts::<u128>(input),
+        Some("decimal") => print_parts::<Decimal>(input),
+        Some(ids) => panic!("Unknown ID type: {}", ids),
+    }
 }
 
-fn parse_input(input: &str) -> (Vec<usize>, Vec<usize>) {
-    let mut a = Vec::with_capacity(input.lines().count());
-    let mut b = Vec::with_capacity(a.capacity());
+fn print_parts<T: Id>(input: &str) {
+    println!("Part 1: {}", total_distance::<T>(input));
+    println!("Part 2: {}", similarity::<T>(input));
+}
 
-    for line in input.lines() {
-        let mut parts = line.split_ascii_whitespace();
-        let Some(left) = parts.next() else {
-            panic!("Invalid input: {}", line);
-        };
-        let Some(right) = parts.next() else {
-            panic!("Invalid input: {}", line);
-        };
+trait Id: Clone + Ord + FromStr + fmt::Display {
+    // Wide enough to add up distances and similarity scores
+    type Total: Clone + Default + Ord + fmt::Display + Add<Output = Self::Total>;
+
+    fn distance(&self, other: &Self) -> Self::Total;
+    fn times(&self, count: usize) -> Self::Total;
+}
 
-        let left: usize = left.parse().expect("Invalid input");
-        let right: usize = right.parse().expect("Invalid input");
+impl Id for usize {
+    type Total = usize;
 
-        a.push(left);
-        b.push(right);
+    fn distance(&self, other: &Self) -> usize {
+        self.abs_diff(*other)
     }
 
-    (a, b)
+    fn times(&self, count: usize) -> usize {
+        self * count
+    }
 }
 
-fn part1(input: &str) -> usize {
-    let (mut a, mut b) = parse_input(input);
+impl Id for i64 {
+    type Total = i128;
 
-    a.sort();
-    b.sort();
+    fn distance(&self, other: &Self) -> i128 {
+        self.abs_diff(*other) as i128
+    }
 
-    let mut sum = 0;
-    for (left, right) in a.iter().zip(b.iter()) {
-        if left > right {
-            sum += left - right;
-        } else {
-            sum += right - left;
+    fn times(&self, count: usize) -> i128 {
+        *self as i128 * count as i128
+    }
+}
+
+impl Id for u128 {
+    type Total = u128;
+
+    fn distance(&self, other: &Self) -> u128 {
+        self.abs_diff(*other)
+    }
+
+    fn times(&self, count: usize) -> u128 {
+        self * count as u128
+    }
+}
+
+// An exact decimal number of any length, like `-12345678901234567890.125`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Decimal {
+    negative: bool,
+    // Least significant first, without leading zeros, so zero has no digits
+    digits: Vec<u8>,
+    // How many of the digits are after the decimal point, without trailing
+    // zeros
+    scale: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InvalidDecimal;
+
+impl Decimal {
+    fn normalized(mut self) -> Self {
+        while self.digits.last() == Some(&0) {
+            self.digits.pop();
         }
+
+        let trailing_zeros = self
+            .digits
+            .iter()
+            .take(self.scale)
+            .take_while(|&&digit| digit == 0)
+            .count();
+        self.digits.drain(..trailing_zeros);
+        self.scale -= trailing_zeros;
+
+        if self.digits.is_empty() {
+            self.negative = false;
+            self.scale = 0;
+        }
+        self
+    }
+
+    // The digits as if there were `scale` digits after the decimal point
+    fn aligned(&self, scale: usize) -> Vec<u8> {
+        let mut digits = vec![0; scale - self.scale];
+        digits.extend_from_slice(&self.digits);
+        digits
     }
 
+    fn abs(self) -> Self {
+        Self {
+            negative: false,
+            ..self
+        }
+    }
+}
+
+fn compare_magnitudes(a: &[u8], b: &[u8]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let digit = a.get(i).unwrap_or(&0) + b.get(i).unwrap_or(&0) + carry;
+        sum.push(digit % 10);
+        carry = digit / 10;
+    }
+    if carry > 0 {
+        sum.push(carry);
+    }
     sum
 }
 
-fn part2(input: &str) -> usize {
-    let (a, b) = parse_input(input);
+// `a` has to be at least as large as `b`
+fn subtract_magnitudes(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &digit) in a.iter().enumerate() {
+        let subtrahend = b.get(i).unwrap_or(&0) + borrow;
+        if digit >= subtrahend {
+            difference.push(digit - subtrahend);
+            borrow = 0;
+        } else {
+            difference.push(digit + 10 - subtrahend);
+            borrow = 1;
+        }
+    }
+    difference
+}
+
+impl Add for Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: Decimal) -> Decimal {
+        let scale = self.scale.max(other.scale);
+        let (a, b) = (self.aligned(scale), other.aligned(scale));
+
+        let (negative, digits) = if self.negative == other.negative {
+            (self.negative, add_magnitudes(&a, &b))
+        } else {
+            match compare_magnitudes(&a, &b) {
+                Ordering::Less => (other.negative, subtract_magnitudes(&b, &a)),
+                _ => (self.negative, subtract_magnitudes(&a, &b)),
+            }
+        };
+
+        Decimal {
+            negative,
+            digits,
+            scale,
+        }
+        .normalized()
+    }
+}
 
-    let b = {
-        let mut map = HashMap::new();
-        for &value in b.iter() {
-            *map.entry(value).or_insert(0) += 1;
+impl Neg for Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal {
+            negative: !self.negative,
+            ..self
         }
-        map
+        .normalized()
+    }
+}
+
+impl Sub for Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: Decimal) -> Decimal {
+        self + -other
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (true, false) => return Ordering::Less,
+            (false, true) => return Ordering::Greater,
+            _ => {}
+        }
+
+        let scale = self.scale.max(other.scale);
+        let magnitudes = compare_magnitudes(&self.aligned(scale), &other.aligned(scale));
+        if self.negative {
+            magnitudes.reverse()
+        } else {
+            magnitudes
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = InvalidDecimal;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, s) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(InvalidDecimal);
+        }
+        if !integer
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+        {
+            return Err(InvalidDecimal);
+        }
+
+        Ok(Decimal {
+            negative,
+            digits: integer
+                .bytes()
+                .chain(fraction.bytes())
+                .rev()
+                .map(|b| b - b'0')
+                .collect(),
+            scale: fraction.len(),
+        }
+        .normalized())
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Pad with zeros so that there's at least one digit before the point
+        let mut digits = self.digits.clone();
+        digits.resize(digits.len().max(self.scale + 1), 0);
+
+        let mut text = String::with_capacity(digits.len() + 2);
+        if self.negative {
+            text.push('-');
+        }
+        for (i, digit) in digits.iter().enumerate().rev() {
+            text.push((b'0' + digit) as char);
+            if i == self.scale && i > 0 {
+                text.push('.');
+            }
+        }
+
+        f.pad(&text)
+    }
+}
+
+impl Id for Decimal {
+    type Total = Decimal;
+
+    fn distance(&self, other: &Self) -> Decimal {
+        (self.clone() - other.clone()).abs()
+    }
+
+    fn times(&self, count: usize) -> Decimal {
+        let mut digits = Vec::with_capacity(self.digits.len() + 20);
+        let mut carry = 0u128;
+        for &digit in &self.digits {
+            let product = digit as u128 * count as u128 + carry;
+            digits.push((product % 10) as u8);
+            carry = product / 10;
+        }
+        while carry > 0 {
+            digits.push((carry % 10) as u8);
+            carry /= 10;
+        }
+
+        Decimal {
+            negative: self.negative,
+            digits,
+            scale: self.scale,
+        }
+        .normalized()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ParseError {
+    InvalidId { line: usize, text: String },
+    TooManyColumns { line: usize },
+    UnequalColumns { left: usize, right: usize },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::InvalidId { line, text } => {
+                write!(f, "line {}: invalid ID {:?}", line, text)
+            }
+            ParseError::TooManyColumns { line } => {
+                write!(f, "line {}: more than two columns", line)
+            }
+            ParseError::UnequalColumns { left, right } => {
+                write!(f, "left list has {} IDs but right list has {}", left, right)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// A line with a single ID belongs to the right list if it's indented, which
+// is how a shorter left list would be laid out
+fn parse_line<T: Id>(number: usize, line: &str) -> Result<(Option<T>, Option<T>), ParseError> {
+    let parse = |text: &str| {
+        text.parse::<T>().map_err(|_| ParseError::InvalidId {
+            line: number,
+            text: text.to_string(),
+        })
+    };
+
+    let line = line.trim_end();
+    let mut parts = line.split_whitespace();
+    let ids = match (parts.next(), parts.next()) {
+        (None, _) => (None, None),
+        (Some(id), None) if line.starts_with(char::is_whitespace) => (None, Some(parse(id)?)),
+        (Some(id), None) => (Some(parse(id)?), None),
+        (Some(left), Some(right)) => (Some(parse(left)?), Some(parse(right)?)),
     };
 
-    let mut similarity = 0;
-    for value in a {
-        similarity += value * b.get(&value).copied().unwrap_or(0);
+    if parts.next().is_some() {
+        return Err(ParseError::TooManyColumns { line: number });
     }
+    Ok(ids)
+}
+
+fn parse_lists<T: Id>(input: &str) -> Result<(Vec<T>, Vec<T>), ParseError> {
+    let mut a = Vec::with_capacity(input.lines().count());
+    let mut b = Vec::with_capacity(a.capacity());
+
+    for (i, line) in input.lines().enumerate() {
+        let (left, right) = parse_line(i + 1, line)?;
+        a.extend(left);
+        b.extend(right);
+    }
+
+    if a.len() != b.len() {
+        return Err(ParseError::UnequalColumns {
+            left: a.len(),
+            right: b.len(),
+        });
+    }
+    Ok((a, b))
+}
+
+fn parse_input<T: Id>(input: &str) -> (Vec<T>, Vec<T>) {
+    parse_lists(input).unwrap_or_else(|error| panic!("Invalid input: {}", error))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Pair<T: Id> {
+    index: usize,
+    left: T,
+    right: T,
+    distance: T::Total,
+}
 
-    similarity
+// Pairs up the smallest values of both lists, then the second smallest, ...
+fn pairs<T: Id>(
+    left: impl IntoIterator<Item = T>,
+    right: impl IntoIterator<Item = T>,
+) -> impl Iterator<Item = Pair<T>> {
+    left.into_iter()
+        .zip(right)
+        .enumerate()
+        .map(|(index, (left, right))| Pair {
+            index,
+            distance: left.distance(&right),
+            left,
+            right,
+        })
+}
+
+fn largest_discrepancies<T: Id>(
+    pairs: impl Iterator<Item = Pair<T>>,
+    count: usize,
+) -> Vec<Pair<T>> {
+    // Min-heap of the largest so far, preferring earlier pairs on ties
+    let mut largest = BinaryHeap::with_capacity(count + 1);
+    for pair in pairs {
+        largest.push(Reverse((pair.distance.clone(), Reverse(pair.index), pair)));
+        if largest.len() > count {
+            largest.pop();
+        }
+    }
+
+    let mut largest: Vec<Pair<T>> = largest
+        .into_iter()
+        .map(|Reverse((_, _, pair))| pair)
+        .collect();
+    largest.sort_by(|a, b| b.distance.cmp(&a.distance).then(a.index.cmp(&b.index)));
+    largest
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Contribution<T> {
+    value: T,
+    left_count: usize,
+    right_count: usize,
+}
+
+impl<T: Id> Contribution<T> {
+    fn score(&self) -> T::Total {
+        self.value.times(self.left_count * self.right_count)
+    }
+}
+
+// Walks both sorted lists side by side, so neither has to be counted into a
+// map first
+fn contributions<T: Id>(
+    left: impl IntoIterator<Item = T>,
+    right: impl IntoIterator<Item = T>,
+) -> impl Iterator<Item = Contribution<T>> {
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    std::iter::from_fn(move || {
+        let value = left.next()?;
+        let left_count = 1 + count_while(&mut left, &value);
+
+        while right.next_if(|right| *right < value).is_some() {}
+        let right_count = count_while(&mut right, &value);
+
+        Some(Contribution {
+            value,
+            left_count,
+            right_count,
+        })
+    })
+}
+
+fn count_while<T: Id>(values: &mut Peekable<impl Iterator<Item = T>>, value: &T) -> usize {
+    let mut count = 0;
+    while values.next_if_eq(value).is_some() {
+        count += 1;
+    }
+    count
+}
+
+// Sorts more values than fit in memory by sorting them in runs of
+// `run_length`, each written to its own file, and merging the runs when read
+struct ExternalSorter<T> {
+    directory: PathBuf,
+    name: String,
+    run_length: usize,
+    buffer: Vec<T>,
+    runs: Vec<PathBuf>,
+}
+
+impl<T: Id> ExternalSorter<T> {
+    fn new(directory: &Path, name: &str, run_length: usize) -> Self {
+        Self {
+            directory: directory.to_path_buf(),
+            name: name.to_string(),
+            run_length,
+            buffer: Vec::with_capacity(run_length),
+            runs: vec![],
+        }
+    }
+
+    fn push(&mut self, value: T) -> io::Result<()> {
+        self.buffer.push(value);
+        if self.buffer.len() >= self.run_length {
+            self.write_run()?;
+        }
+        Ok(())
+    }
+
+    fn write_run(&mut self) -> io::Result<()> {
+        self.buffer.sort_unstable();
+
+        let path = self.directory.join(format!(
+            "{}-{}-{}.run",
+            self.name,
+            std::process::id(),
+            self.runs.len()
+        ));
+        let mut file = BufWriter::new(File::create(&path)?);
+        for value in self.buffer.drain(..) {
+            writeln!(file, "{}", value)?;
+        }
+        file.flush()?;
+
+        self.runs.push(path);
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<SortedRuns<T>> {
+        if !self.buffer.is_empty() {
+            self.write_run()?;
+        }
+        Ok(SortedRuns {
+            runs: std::mem::take(&mut self.runs),
+            values: PhantomData,
+        })
+    }
+}
+
+// Clean up if sorting fails part way
+impl<T> Drop for ExternalSorter<T> {
+    fn drop(&mut self) {
+        remove_runs(&self.runs);
+    }
+}
+
+struct SortedRuns<T> {
+    runs: Vec<PathBuf>,
+    values: PhantomData<T>,
+}
+
+impl<T: Id> SortedRuns<T> {
+    // Can be called again to go over the values a second time
+    fn iter(&self) -> io::Result<Merge<T>> {
+        let mut readers = Vec::with_capacity(self.runs.len());
+        let mut heads = BinaryHeap::with_capacity(self.runs.len());
+        for path in &self.runs {
+            let mut lines = BufReader::new(File::open(path)?).lines();
+            if let Some(value) = next_value(&mut lines) {
+                heads.push(Reverse((value, readers.len())));
+            }
+            readers.push(lines);
+        }
+
+        Ok(Merge { readers, heads })
+    }
+}
+
+impl<T> Drop for SortedRuns<T> {
+    fn drop(&mut self) {
+        remove_runs(&self.runs);
+    }
+}
+
+fn remove_runs(runs: &[PathBuf]) {
+    for path in runs {
+        let _ = fs::remove_file(path);
+    }
+}
+
+struct Merge<T> {
+    readers: Vec<Lines<BufReader<File>>>,
+    // Smallest value not yet returned from each run
+    heads: BinaryHeap<Reverse<(T, usize)>>,
+}
+
+impl<T: Id> Iterator for Merge<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let Reverse((value, run)) = self.heads.pop()?;
+        if let Some(next) = next_value(&mut self.readers[run]) {
+            self.heads.push(Reverse((next, run)));
+        }
+        Some(value)
+    }
+}
+
+fn next_value<T: Id>(lines: &mut Lines<BufReader<File>>) -> Option<T> {
+    let line = lines.next()?.expect("Couldn't read sorted run");
+    Some(line.parse().ok().expect("Corrupted sorted run"))
+}
+
+const RUN_LENGTH: usize = 1 << 20;
+
+fn sort_lists<T: Id>(input: impl BufRead) -> io::Result<(SortedRuns<T>, SortedRuns<T>)> {
+    let directory = std::env::temp_dir();
+    let mut left = ExternalSorter::new(&directory, "day01-left", RUN_LENGTH);
+    let mut right = ExternalSorter::new(&directory, "day01-right", RUN_LENGTH);
+    let (mut left_count, mut right_count) = (0, 0);
+
+    for (i, line) in input.lines().enumerate() {
+        let (a, b) = parse_line(i + 1, &line?)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        if let Some(a) = a {
+            left.push(a)?;
+            left_count += 1;
+        }
+        if let Some(b) = b {
+            right.push(b)?;
+            right_count += 1;
+        }
+    }
+
+    if left_count != right_count {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            ParseError::UnequalColumns {
+                left: left_count,
+                right: right_count,
+            },
+        ));
+    }
+    Ok((left.finish()?, right.finish()?))
+}
+
+fn reconcile<T: Id>(path: &str, largest: usize) {
+    let input = BufReader::new(File::open(path).expect("Couldn't open input"));
+    let (left, right) =
+        sort_lists::<T>(input).unwrap_or_else(|error| panic!("Couldn't sort lists: {}", error));
+    print_reconciliation(&left, &right, largest).expect("Couldn't write output");
+}
+
+fn print_reconciliation<T: Id>(
+    left: &SortedRuns<T>,
+    right: &SortedRuns<T>,
+    largest: usize,
+) -> io::Result<()> {
+    let mut output = BufWriter::new(io::stdout().lock());
+
+    writeln!(output, "Pairs:")?;
+    let mut total_distance = T::Total::default();
+    for pair in pairs(left.iter()?, right.iter()?) {
+        writeln!(
+            output,
+            "{:>8}: {} {} (distance {})",
+            pair.index, pair.left, pair.right, pair.distance
+        )?;
+        total_distance = total_distance + pair.distance;
+    }
+
+    writeln!(output, "Largest discrepancies:")?;
+    for pair in largest_discrepancies(pairs(left.iter()?, right.iter()?), largest) {
+        writeln!(
+            output,
+            "{:>8}: {} {} (distance {})",
+            pair.index, pair.left, pair.right, pair.distance
+        )?;
+    }
+
+    writeln!(output, "Similarity:")?;
+    let mut similarity = T::Total::default();
+    for contribution in contributions(left.iter()?, right.iter()?) {
+        writeln!(
+            output,
+            "{:>8} x{} in left, x{} in right: {}",
+            contribution.value,
+            contribution.left_count,
+            contribution.right_count,
+            contribution.score()
+        )?;
+        similarity = similarity + contribution.score();
+    }
+
+    writeln!(output, "Total distance: {}", total_distance)?;
+    writeln!(output, "Similarity score: {}", similarity)?;
+    output.flush()
+}
+
+fn total_distance<T: Id>(input: &str) -> T::Total {
+    let (mut a, mut b) = parse_input::<T>(input);
+
+    a.sort();
+    b.sort();
+
+    pairs(a, b).fold(T::Total::default(), |sum, pair| sum + pair.distance)
+}
+
+fn similarity<T: Id>(input: &str) -> T::Total {
+    let (mut a, mut b) = parse_input::<T>(input);
+
+    a.sort();
+    b.sort();
+
+    contributions(a, b).fold(T::Total::default(), |sum, contribution| {
+        sum + contribution.score()
+    })
+}
+
+fn part1(input: &str) -> usize {
+    total_distance::<usize>(input)
+}
+
+fn part2(input: &str) -> usize {
+    similarity::<usize>(input)
 }
 
 #[cfg(test)]
@@ -87,4 +755,144 @@ mod tests {
     fn test_part2() {
         assert_eq!(part2(TEST_INPUT), 31);
     }
+
+    #[test]
+    fn test_reconciliation() {
+        let (mut a, mut b) = parse_input::<usize>(TEST_INPUT);
+        a.sort();
+        b.sort();
+
+        let largest = largest_discrepancies(pairs(a.clone(), b.clone()), 3);
+        assert_eq!(
+            largest
+                .iter()
+                .map(|pair| (pair.index, pair.left, pair.right, pair.distance))
+                .collect::<Vec<_>>(),
+            [(5, 4, 9, 5), (0, 1, 3, 2), (4, 3, 5, 2)]
+        );
+
+        let scores: Vec<_> = contributions(a, b)
+            .map(|contribution| (contribution.value, contribution.score()))
+            .collect();
+        assert_eq!(scores, [(1, 0), (2, 0), (3, 27), (4, 4)]);
+    }
+
+    #[test]
+    fn test_external_sort() {
+        let directory = std::env::temp_dir();
+        let mut sorter = ExternalSorter::new(&directory, "day01-test-external-sort", 7);
+        let mut values = vec![];
+        let mut seed = 42usize;
+        for _ in 0..100 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            values.push(seed >> 54);
+            sorter.push(seed >> 54).unwrap();
+        }
+
+        let runs = sorter.finish().unwrap();
+        assert_eq!(runs.runs.len(), 15);
+
+        values.sort();
+        assert_eq!(runs.iter().unwrap().collect::<Vec<_>>(), values);
+        // Going over the runs doesn't use them up
+        assert_eq!(runs.iter().unwrap().count(), 100);
+
+        let paths = runs.runs.clone();
+        drop(runs);
+        assert!(paths.iter().all(|path| !path.exists()));
+    }
+
+    #[test]
+    fn test_sort_lists() {
+        let (left, right) = sort_lists::<usize>(TEST_INPUT.as_bytes()).unwrap();
+        let total: usize = pairs(left.iter().unwrap(), right.iter().unwrap())
+            .map(|pair| pair.distance)
+            .sum();
+        let similarity: usize = contributions(left.iter().unwrap(), right.iter().unwrap())
+            .map(|contribution| contribution.score())
+            .sum();
+
+        assert_eq!(total, 11);
+        assert_eq!(similarity, 31);
+
+        let error = sort_lists::<usize>("1   2\n3\n".as_bytes()).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "left list has 2 IDs but right list has 1"
+        );
+    }
+
+    #[test]
+    fn test_id_types() {
+        const SIGNED_INPUT: &str = "-3   4
+4   -3
+2   5
+-1   3
+3   -9
+3   3";
+
+        assert_eq!(total_distance::<i64>(SIGNED_INPUT), 11);
+        assert_eq!(similarity::<i64>(SIGNED_INPUT), -3 + 12 + 4);
+        assert_eq!(total_distance::<u128>(TEST_INPUT), 11);
+        assert_eq!(similarity::<u128>(TEST_INPUT), 31);
+        assert_eq!(similarity::<Decimal>(SIGNED_INPUT).to_string(), "13");
+
+        // Too long for any integer type
+        let long_input = "100000000000000000000000000000000000000000   99999999999999999999999999999999999999999.5
+-0.25   -0.5
+100000000000000000000000000000000000000000   -0.250";
+        assert_eq!(
+            total_distance::<Decimal>(long_input).to_string(),
+            "100000000000000000000000000000000000000001"
+        );
+        assert_eq!(similarity::<Decimal>(long_input).to_string(), "-0.25");
+    }
+
+    #[test]
+    fn test_decimal() {
+        let decimal = |text: &str| text.parse::<Decimal>().unwrap();
+
+        assert_eq!(decimal("007.500").to_string(), "7.5");
+        assert_eq!(decimal("-.05").to_string(), "-0.05");
+        assert_eq!(decimal("-0.0").to_string(), "0");
+        assert_eq!(decimal("+12").to_string(), "12");
+        assert!("1.2.3".parse::<Decimal>().is_err());
+        assert!("-".parse::<Decimal>().is_err());
+        assert!("1e5".parse::<Decimal>().is_err());
+
+        assert!(decimal("-10") < decimal("-9.99"));
+        assert!(decimal("0.1") < decimal("0.11"));
+        assert!(decimal("-0.5") < decimal("0"));
+        assert_eq!(decimal("2.50"), decimal("2.5"));
+
+        assert_eq!((decimal("0.1") - decimal("10")).to_string(), "-9.9");
+        assert_eq!(decimal("99.99").times(101).to_string(), "10098.99");
+        assert_eq!(
+            decimal("-1.5").distance(&decimal("2.25")).to_string(),
+            "3.75"
+        );
+    }
+
+    #[test]
+    fn test_unequal_columns() {
+        assert_eq!(
+            parse_lists::<usize>("1   2\n3\n4   5"),
+            Err(ParseError::UnequalColumns { left: 3, right: 2 })
+        );
+        assert_eq!(
+            parse_lists::<usize>("1   2\n3\n    4"),
+            Ok((vec![1, 3], vec![2, 4]))
+        );
+        assert_eq!(
+            parse_lists::<usize>("1   2\n3   x"),
+            Err(ParseError::InvalidId {
+                line: 2,
+                text: "x".to_string()
+            })
+        );
+        assert_eq!(
+            parse_lists::<usize>("1 2 3").unwrap_err().to_string(),
+            "line 1: more than two columns"
+        );
+    }
 }
@@ -1,57 +1,230 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
 fn main() {
     let input = include_str!("input.txt");
 
+    if std::env::args().nth(1).as_deref() == Some("dampen") {
+        let rules = Rules {
+            removable: std::env::args()
+                .nth(2)
+                .map_or(1, |removable| removable.parse().unwrap()),
+            ..Rules::part1()
+        };
+
+        for (line, report) in input.lines().enumerate() {
+            let levels = parse_report(report);
+            match dampen(&levels, &rules) {
+                Some(removed) if removed.is_empty() => println!("{}: safe", line + 1),
+                Some(removed) => {
+                    let removed: Vec<_> = removed
+                        .iter()
+                        .map(|&i| format!("{} (index {})", levels[i], i))
+                        .collect();
+                    println!("{}: remove {}", line + 1, removed.join(", "));
+                }
+                None => println!("{}: unsafe", line + 1),
+            }
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("verdicts") {
+        for (line, report) in input.lines().enumerate() {
+            let verdict = check_report(parse_report(report).into_iter(), &Rules::part1());
+            println!("{}: {:<24} {}", line + 1, report, verdict);
+        }
+
+        return;
+    }
+
     println!("Part 1: {}", part1(input));
     println!("Part 2: {}", part2(input));
 }
 
-fn part1(input: &str) -> usize {
-    let mut safe = 0;
-
-    for report in input.lines() {
-        if !is_report_safe(
-            report
-                .split_ascii_whitespace()
-                .map(|x| x.parse::<isize>().unwrap()),
-        ) {
-            continue;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rules {
+    // Allowed difference between adjacent levels, in the direction of the
+    // report if it has to be monotonic
+    diff: RangeInclusive<isize>,
+    monotonic: bool,
+    // How many levels the problem dampener may remove
+    removable: usize,
+}
+
+impl Rules {
+    fn part1() -> Self {
+        Self {
+            diff: 1..=3,
+            monotonic: true,
+            removable: 0,
         }
+    }
 
-        safe += 1;
+    fn part2() -> Self {
+        Self {
+            removable: 1,
+            ..Self::part1()
+        }
     }
+}
+
+fn parse_report(report: &str) -> Vec<isize> {
+    report
+        .split_ascii_whitespace()
+        .map(|x| x.parse::<isize>().unwrap())
+        .collect()
+}
+
+fn count_safe(input: &str, rules: &Rules) -> usize {
+    input
+        .lines()
+        .filter(|report| dampen(&parse_report(report), rules).is_some())
+        .count()
+}
 
-    safe
+fn part1(input: &str) -> usize {
+    count_safe(input, &Rules::part1())
 }
 
 fn part2(input: &str) -> usize {
-    let mut safe = 0;
-
-    for report in input.lines() {
-        // First check if the report is safe as it is
-        let levels = report
-            .split_ascii_whitespace()
-            .map(|x| x.parse::<isize>().unwrap())
-            .collect::<Vec<_>>();
-        if is_report_safe(levels.iter().copied()) {
-            safe += 1;
-            continue;
+    count_safe(input, &Rules::part2())
+}
+
+// Returns the fewest levels (by index) that have to be removed to make the
+// report safe, or `None` if it takes more than the rules allow
+fn dampen(levels: &[isize], rules: &Rules) -> Option<Vec<usize>> {
+    if check_report(levels.iter().copied(), rules).is_safe() {
+        return Some(vec![]);
+    }
+    if rules.removable == 0 {
+        return None;
+    }
+
+    if rules.monotonic {
+        let increasing = dampen_with(levels, rules.removable, |a, b| {
+            rules.diff.contains(&(b - a))
+        });
+        let decreasing = dampen_with(levels, rules.removable, |a, b| {
+            rules.diff.contains(&(a - b))
+        });
+        match (increasing, decreasing) {
+            (Some(increasing), Some(decreasing)) if decreasing.len() < increasing.len() => {
+                Some(decreasing)
+            }
+            (None, decreasing) => decreasing,
+            (increasing, _) => increasing,
         }
+    } else {
+        dampen_with(levels, rules.removable, |a, b| {
+            rules.diff.contains(&(b - a).abs())
+        })
+    }
+}
 
-        // Try with the problem dampener, allowing one value to be removed
-        for i in 0..levels.len() {
-            let mut levels = levels.clone();
-            levels.remove(i);
-            if is_report_safe(levels.iter().copied()) {
-                safe += 1;
-                break;
+// With at most `removable` levels removed, the level kept before any other is
+// at most `removable + 1` positions back. So track, for every level kept and
+// number of levels removed before it, which level was kept before that,
+// which is O(n * removable^2) rather than trying every subset.
+fn dampen_with(
+    levels: &[isize],
+    removable: usize,
+    allowed: impl Fn(isize, isize) -> bool,
+) -> Option<Vec<usize>> {
+    if levels.len() <= 1 {
+        return Some(vec![]);
+    }
+
+    // previous[i][r] is `Some(kept)` if level `i` can be kept with `r` levels
+    // removed before it, where `kept` is the level kept just before it
+    let mut previous: Vec<Vec<Option<Option<usize>>>> =
+        vec![vec![None; removable + 1]; levels.len()];
+
+    for i in 0..levels.len() {
+        if i <= removable {
+            previous[i][i] = Some(None);
+        }
+
+        for j in i.saturating_sub(removable + 1)..i {
+            if !allowed(levels[j], levels[i]) {
+                continue;
+            }
+
+            let skipped = i - j - 1;
+            for r in 0..=removable - skipped {
+                if previous[j][r].is_some() && previous[i][r + skipped].is_none() {
+                    previous[i][r + skipped] = Some(Some(j));
+                }
             }
         }
     }
 
-    safe
+    // Everything after the last level kept is removed too
+    let (mut last, mut removed) = (0..levels.len())
+        .flat_map(|i| (0..=removable).map(move |r| (i, r)))
+        .filter(|&(i, r)| previous[i][r].is_some() && r + levels.len() - 1 - i <= removable)
+        .min_by_key(|&(i, r)| r + levels.len() - 1 - i)?;
+
+    let mut kept = vec![false; levels.len()];
+    loop {
+        kept[last] = true;
+        match previous[last][removed].unwrap() {
+            Some(before) => {
+                removed -= last - before - 1;
+                last = before;
+            }
+            None => break,
+        }
+    }
+
+    Some((0..levels.len()).filter(|&i| !kept[i]).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    DirectionChange,
+    OutOfRange,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Safe,
+    // `index` is the level that broke the rules, and `diff` how far it is
+    // from the level before it
+    Unsafe {
+        index: usize,
+        problem: Problem,
+        diff: isize,
+    },
 }
 
-fn is_report_safe(levels: impl Iterator<Item = isize>) -> bool {
+impl Verdict {
+    fn is_safe(&self) -> bool {
+        *self == Verdict::Safe
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Verdict::Safe => write!(f, "safe"),
+            Verdict::Unsafe {
+                index,
+                problem,
+                diff,
+            } => {
+                let problem = match problem {
+                    Problem::DirectionChange => "direction change",
+                    Problem::OutOfRange => "difference out of range",
+                };
+                write!(f, "unsafe at index {}: {} ({:+})", index, problem, diff)
+            }
+        }
+    }
+}
+
+fn check_report(levels: impl Iterator<Item = isize>, rules: &Rules) -> Verdict {
     #[derive(Debug)]
     enum ReportState {
         New,
@@ -60,45 +233,53 @@ fn is_report_safe(levels: impl Iterator<Item = isize>) -> bool {
         Decreasing(isize),
     }
 
-    const SAFE_DIFF_RANGE: std::ops::RangeInclusive<isize> = 1..=3;
-
     let mut report_state = ReportState::New;
 
-    for level in levels {
+    for (index, level) in levels.enumerate() {
+        let unsafe_because = |problem, prev| Verdict::Unsafe {
+            index,
+            problem,
+            diff: level - prev,
+        };
+
         match report_state {
             ReportState::New => {
                 report_state = ReportState::Started(level);
             }
             ReportState::Started(prev) => {
-                let diff = (level - prev).abs();
-                if !SAFE_DIFF_RANGE.contains(&diff) {
-                    return false;
-                }
-
-                report_state = if level > prev {
+                let diff = level - prev;
+                report_state = if !rules.monotonic {
+                    if !rules.diff.contains(&diff.abs()) {
+                        return unsafe_because(Problem::OutOfRange, prev);
+                    }
+                    ReportState::Started(level)
+                } else if diff == 0 && rules.diff.contains(&0) {
+                    // A level that stays the same doesn't pick a direction yet
+                    ReportState::Started(level)
+                } else if rules.diff.contains(&diff) {
                     ReportState::Increasing(level)
-                } else {
+                } else if rules.diff.contains(&-diff) {
                     ReportState::Decreasing(level)
+                } else {
+                    return unsafe_because(Problem::OutOfRange, prev);
                 };
             }
             ReportState::Increasing(prev) => {
                 if level < prev {
-                    return false;
+                    return unsafe_because(Problem::DirectionChange, prev);
                 }
-                let diff = (level - prev).abs();
-                if !SAFE_DIFF_RANGE.contains(&diff) {
-                    return false;
+                if !rules.diff.contains(&(level - prev)) {
+                    return unsafe_because(Problem::OutOfRange, prev);
                 }
 
                 report_state = ReportState::Increasing(level);
             }
             ReportState::Decreasing(prev) => {
                 if level > prev {
-                    return false;
+                    return unsafe_because(Problem::DirectionChange, prev);
                 }
-                let diff = (level - prev).abs();
-                if !SAFE_DIFF_RANGE.contains(&diff) {
-                    return false;
+                if !rules.diff.contains(&(prev - level)) {
+                    return unsafe_because(Problem::OutOfRange, prev);
                 }
 
                 report_state = ReportState::Decreasing(level);
@@ -106,7 +287,7 @@ fn is_report_safe(levels: impl Iterator<Item = isize>) -> bool {
         }
     }
 
-    true
+    Verdict::Safe
 }
 
 #[cfg(test)]
@@ -129,4 +310,160 @@ mod tests {
     fn test_part2() {
         assert_eq!(part2(TEST_INPUT), 4);
     }
+
+    #[test]
+    fn test_dampen() {
+        let removed: Vec<_> = TEST_INPUT
+            .lines()
+            .map(|report| dampen(&parse_report(report), &Rules::part2()))
+            .collect();
+        assert_eq!(
+            removed,
+            [
+                Some(vec![]),
+                None,
+                None,
+                Some(vec![2]),
+                Some(vec![3]),
+                Some(vec![])
+            ]
+        );
+
+        let rules = Rules {
+            removable: 2,
+            ..Rules::part1()
+        };
+        assert_eq!(dampen(&[1, 9, 2, 9, 3], &rules), Some(vec![1, 3]));
+        assert_eq!(dampen(&[1, 9, 9, 9, 3], &rules), None);
+        assert_eq!(dampen(&[5, 1, 2, 3, 0], &rules), Some(vec![0, 4]));
+
+        let rules = Rules {
+            diff: 0..=5,
+            monotonic: false,
+            removable: 1,
+        };
+        assert_eq!(dampen(&[1, 6, 6, 2, 20, 0], &rules), Some(vec![4]));
+
+        // Equal levels are fine either way round
+        let rules = Rules {
+            diff: 0..=3,
+            monotonic: true,
+            removable: 1,
+        };
+        assert_eq!(dampen(&[5, 5, 3], &rules), Some(vec![]));
+        assert!(check_report([5, 5, 3].into_iter(), &rules).is_safe());
+        assert_eq!(dampen(&[5, 5, 6, 3, 2], &rules), Some(vec![2]));
+    }
+
+    #[test]
+    fn test_dampen_matches_brute_force() {
+        fn brute_force(levels: &[isize], rules: &Rules) -> Option<usize> {
+            (0..1usize << levels.len())
+                .filter(|mask| mask.count_ones() as usize <= rules.removable)
+                .filter(|mask| {
+                    let kept = (0..levels.len())
+                        .filter(|i| mask & (1 << i) == 0)
+                        .map(|i| levels[i]);
+                    check_report(kept, rules).is_safe()
+                })
+                .map(|mask| mask.count_ones() as usize)
+                .min()
+        }
+
+        // Small pseudo-random reports, so that most need a few removals
+        let mut seed = 12345u64;
+        let rule_sets = [
+            Rules::part1(),
+            Rules {
+                diff: 0..=2,
+                ..Rules::part1()
+            },
+            Rules {
+                diff: 0..=1,
+                monotonic: false,
+                removable: 0,
+            },
+        ];
+        for (rules, removable) in rule_sets
+            .iter()
+            .flat_map(|rules| (0..4).map(move |removable| (rules, removable)))
+        {
+            let rules = Rules {
+                removable,
+                ..rules.clone()
+            };
+            for _ in 0..500 {
+                let levels: Vec<isize> = (0..8)
+                    .map(|_| {
+                        seed = seed
+                            .wrapping_mul(6364136223846793005)
+                            .wrapping_add(1442695040888963407);
+                        (seed >> 59) as isize
+                    })
+                    .collect();
+
+                let removed = dampen(&levels, &rules);
+                assert_eq!(removed.as_ref().map(Vec::len), brute_force(&levels, &rules));
+                if let Some(removed) = removed {
+                    let kept = (0..levels.len())
+                        .filter(|i| !removed.contains(i))
+                        .map(|i| levels[i]);
+                    assert!(check_report(kept, &rules).is_safe(), "{:?}", levels);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verdicts() {
+        let verdicts: Vec<_> = TEST_INPUT
+            .lines()
+            .map(|report| check_report(parse_report(report).into_iter(), &Rules::part1()))
+            .collect();
+        assert_eq!(
+            verdicts,
+            [
+                Verdict::Safe,
+                Verdict::Unsafe {
+                    index: 2,
+                    problem: Problem::OutOfRange,
+                    diff: 5
+                },
+                Verdict::Unsafe {
+                    index: 3,
+                    problem: Problem::OutOfRange,
+                    diff: -4
+                },
+                Verdict::Unsafe {
+                    index: 2,
+                    problem: Problem::DirectionChange,
+                    diff: -1
+                },
+                Verdict::Unsafe {
+                    index: 3,
+                    problem: Problem::OutOfRange,
+                    diff: 0
+                },
+                Verdict::Safe,
+            ]
+        );
+
+        // A repeated first pair has no direction to change from
+        assert_eq!(
+            check_report([3, 3, 2].into_iter(), &Rules::part1()),
+            Verdict::Unsafe {
+                index: 1,
+                problem: Problem::OutOfRange,
+                diff: 0
+            }
+        );
+        assert_eq!(
+            check_report([1, 2, 6, 5].into_iter(), &Rules::part1()).to_string(),
+            "unsafe at index 2: difference out of range (+4)"
+        );
+        assert_eq!(
+            check_report([1, 2, 5, 1].into_iter(), &Rules::part1()).to_string(),
+            "unsafe at index 3: direction change (-4)"
+        );
+    }
 }
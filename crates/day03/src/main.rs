@@ -1,49 +1,491 @@
+use std::fs::File;
+use std::io::{self, Read};
+
 fn main() {
     let input = include_str!("input.txt");
 
+    if std::env::args().nth(1).as_deref() == Some("stream") {
+        let path = std::env::args().nth(2).expect("Usage: day03 stream <path>");
+        let interpreters = [
+            Interpreter::new(vec![Box::new(Mul)]),
+            Interpreter::new(vec![Box::new(Mul), Box::new(Do), Box::new(Dont)]),
+        ];
+
+        for (part, interpreter) in interpreters.iter().enumerate() {
+            let file = File::open(&path).expect("Couldn't open input");
+            let state = interpreter
+                .run_reader(file, STREAM_CHUNK_SIZE)
+                .expect("Couldn't read input");
+            println!("Part {}: {}", part + 1, state.sum);
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("annotate") {
+        let markup = match std::env::args().nth(2).as_deref() {
+            Some("html") => Markup::Html,
+            _ => Markup::Ansi,
+        };
+        let interpreter = Interpreter::new(vec![Box::new(Mul), Box::new(Do), Box::new(Dont)]);
+        println!("{}", interpreter.annotate(input.as_bytes(), markup));
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("instructions") {
+        let interpreter = Interpreter::new(vec![
+            Box::new(Mul),
+            Box::new(Do),
+            Box::new(Dont),
+            Box::new(Add),
+        ]);
+
+        for call in interpreter.parse(input.as_bytes()) {
+            println!(
+                "{:>8}: {}",
+                call.offset,
+                &input[call.offset..call.offset + call.length]
+            );
+        }
+
+        return;
+    }
+
     println!("Part 1: {}", part1(input));
     println!("Part 2: {}", part2(input));
 }
 
-fn part1(input: &str) -> usize {
-    let regex = regex::Regex::new(r"mul\((\d{1,3}),(\d{1,3})\)").unwrap();
-    let mut sum = 0;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TokenKind {
+    Word,
+    Number,
+    OpenParen,
+    CloseParen,
+    Comma,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a [u8],
+    offset: usize,
+}
+
+// Works on bytes rather than characters, since the memory is corrupted and
+// might not even be valid UTF-8
+fn tokenize(input: &[u8]) -> Vec<Token<'_>> {
+    let mut tokens = vec![];
+    let mut offset = 0;
 
-    for cap in regex.captures_iter(input) {
-        let x: usize = cap[1].parse().unwrap();
-        let y: usize = cap[2].parse().unwrap();
+    while offset < input.len() {
+        let rest = &input[offset..];
+        let (kind, length) = match rest[0] {
+            b'(' => (TokenKind::OpenParen, 1),
+            b')' => (TokenKind::CloseParen, 1),
+            b',' => (TokenKind::Comma, 1),
+            byte if byte.is_ascii_digit() => (
+                TokenKind::Number,
+                rest.iter().take_while(|b| b.is_ascii_digit()).count(),
+            ),
+            byte if is_word_byte(byte) => (
+                TokenKind::Word,
+                rest.iter().take_while(|&&b| is_word_byte(b)).count(),
+            ),
+            _ => (TokenKind::Other, 1),
+        };
 
-        sum += x * y;
+        tokens.push(Token {
+            kind,
+            text: &rest[..length],
+            offset,
+        });
+        offset += length;
     }
 
-    sum
+    tokens
 }
 
-fn part2(input: &str) -> usize {
-    let regex = regex::Regex::new(r"mul\((\d{1,3}),(\d{1,3})\)|do\(\)|don't\(\)").unwrap();
-    let mut sum = 0;
-    let mut enabled = true;
+fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphabetic() || byte == b'_' || byte == b'\''
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct State {
+    enabled: bool,
+    sum: usize,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            sum: 0,
+        }
+    }
+}
+
+trait Instruction {
+    fn name(&self) -> &'static str;
+    fn arity(&self) -> usize;
+    fn execute(&self, arguments: &[usize], state: &mut State);
+}
+
+struct Mul;
+struct Do;
+struct Dont;
+struct Add;
+
+impl Instruction for Mul {
+    fn name(&self) -> &'static str {
+        "mul"
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn execute(&self, arguments: &[usize], state: &mut State) {
+        if state.enabled {
+            state.sum += arguments[0] * arguments[1];
+        }
+    }
+}
+
+impl Instruction for Do {
+    fn name(&self) -> &'static str {
+        "do"
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, _: &[usize], state: &mut State) {
+        state.enabled = true;
+    }
+}
+
+impl Instruction for Dont {
+    fn name(&self) -> &'static str {
+        "don't"
+    }
+
+    fn arity(&self) -> usize {
+        0
+    }
+
+    fn execute(&self, _: &[usize], state: &mut State) {
+        state.enabled = false;
+    }
+}
+
+impl Instruction for Add {
+    fn name(&self) -> &'static str {
+        "add"
+    }
+
+    fn arity(&self) -> usize {
+        2
+    }
+
+    fn execute(&self, arguments: &[usize], state: &mut State) {
+        if state.enabled {
+            state.sum += arguments[0] + arguments[1];
+        }
+    }
+}
+
+// Arguments are numbers of one to three digits
+const MAX_ARGUMENT_DIGITS: usize = 3;
+
+const STREAM_CHUNK_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Call {
+    instruction: usize,
+    offset: usize,
+    length: usize,
+    arguments: Vec<usize>,
+}
+
+struct Interpreter {
+    instructions: Vec<Box<dyn Instruction>>,
+}
+
+impl Interpreter {
+    fn new(instructions: Vec<Box<dyn Instruction>>) -> Self {
+        Self { instructions }
+    }
+
+    fn parse(&self, input: &[u8]) -> Vec<Call> {
+        let tokens = tokenize(input);
+
+        (0..tokens.len())
+            .filter_map(|i| self.parse_call(&tokens[i..]))
+            .collect()
+    }
+
+    // Garbage can run straight into an instruction (`xmul(2,4)`), so the
+    // instruction only has to match the end of a word
+    fn parse_call(&self, tokens: &[Token]) -> Option<Call> {
+        let word = tokens[0];
+        if word.kind != TokenKind::Word {
+            return None;
+        }
 
-    for cap in regex.captures_iter(input) {
-        if cap[0].starts_with("don't") {
-            enabled = false;
-            continue;
-        } else if cap[0].starts_with("do") {
-            enabled = true;
-            continue;
+        let (instruction, name) = self
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(i, instruction)| (i, instruction.name().as_bytes()))
+            .filter(|(_, name)| word.text.ends_with(name))
+            .max_by_key(|(_, name)| name.len())?;
+        let arity = self.instructions[instruction].arity();
+
+        let mut rest = tokens[1..].iter();
+        if rest.next()?.kind != TokenKind::OpenParen {
+            return None;
+        }
+
+        let mut arguments = Vec::with_capacity(arity);
+        for i in 0..arity {
+            if i > 0 && rest.next()?.kind != TokenKind::Comma {
+                return None;
+            }
+
+            let number = rest.next()?;
+            if number.kind != TokenKind::Number || number.text.len() > MAX_ARGUMENT_DIGITS {
+                return None;
+            }
+            arguments.push(std::str::from_utf8(number.text).ok()?.parse().ok()?);
+        }
+
+        let close = rest.next()?;
+        if close.kind != TokenKind::CloseParen {
+            return None;
         }
 
-        if !enabled {
-            continue;
+        let offset = word.offset + word.text.len() - name.len();
+        Some(Call {
+            instruction,
+            offset,
+            length: close.offset + 1 - offset,
+            arguments,
+        })
+    }
+
+    fn run(&self, input: &[u8]) -> State {
+        let mut state = State::default();
+        for call in self.parse(input) {
+            self.instructions[call.instruction].execute(&call.arguments, &mut state);
         }
 
-        let x: usize = cap[1].parse().unwrap();
-        let y: usize = cap[2].parse().unwrap();
+        state
+    }
 
-        sum += x * y;
+    fn max_call_length(&self) -> usize {
+        self.instructions
+            .iter()
+            .map(|instruction| {
+                let arity = instruction.arity();
+                // name(arguments,separated,by,commas)
+                instruction.name().len() + 2 + arity * MAX_ARGUMENT_DIGITS + arity.saturating_sub(1)
+            })
+            .max()
+            .unwrap_or(0)
     }
 
-    sum
+    // Like `run`, but reads the input `chunk_size` bytes at a time. The last
+    // few bytes of each chunk might hold the start of an instruction that
+    // continues in the next one, so those are held back until then.
+    fn run_reader(&self, mut reader: impl Read, chunk_size: usize) -> io::Result<State> {
+        let held_back = self.max_call_length();
+        let longest_name = self
+            .instructions
+            .iter()
+            .map(|instruction| instruction.name().len())
+            .max()
+            .unwrap_or(0);
+        let mut state = State::default();
+        let mut buffer = Vec::with_capacity(chunk_size + held_back);
+        let mut chunk = vec![0; chunk_size];
+
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            buffer.extend_from_slice(&chunk[..read]);
+
+            // A call that starts before this point fits in the buffer, so
+            // whether it's valid can't depend on what's still to come
+            let mut settled = if read == 0 {
+                buffer.len()
+            } else {
+                buffer.len().saturating_sub(held_back)
+            };
+            // Names only have to match the end of a word, so a word cut in two
+            // could match a shorter name once its start is gone. Any word
+            // longer than every name matches the same names, though, so only
+            // that much of it has to be kept.
+            if settled < buffer.len() && is_word_byte(buffer[settled]) {
+                let keep_from = settled.saturating_sub(longest_name + 1);
+                while settled > keep_from && is_word_byte(buffer[settled - 1]) {
+                    settled -= 1;
+                }
+            }
+
+            for call in self.parse(&buffer) {
+                if call.offset < settled {
+                    self.instructions[call.instruction].execute(&call.arguments, &mut state);
+                }
+            }
+
+            if read == 0 {
+                return Ok(state);
+            }
+            buffer.drain(..settled);
+        }
+    }
+
+    fn annotate(&self, input: &[u8], markup: Markup) -> String {
+        let mut output = String::new();
+        if markup == Markup::Html {
+            output.push_str(HTML_HEADER);
+        }
+
+        let mut state = State::default();
+        let mut position = 0;
+        for call in self.parse(input) {
+            let instruction = &self.instructions[call.instruction];
+            let before = state;
+            instruction.execute(&call.arguments, &mut state);
+
+            markup.text(&input[position..call.offset], &mut output);
+            position = call.offset + call.length;
+            markup.call(
+                &input[call.offset..position],
+                Effect::of(instruction.arity(), before, state),
+                state.sum,
+                &mut output,
+            );
+        }
+        markup.text(&input[position..], &mut output);
+
+        if markup == Markup::Html {
+            output.push_str("</pre>");
+        }
+        output
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Markup {
+    Ansi,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Effect {
+    Enable,
+    Disable,
+    Counted,
+    Skipped,
+}
+
+impl Effect {
+    // Instructions that take arguments are the ones that add to the sum, so
+    // they're the ones `don't()` can switch off
+    fn of(arity: usize, before: State, after: State) -> Self {
+        if arity == 0 || before.enabled != after.enabled {
+            if after.enabled {
+                Effect::Enable
+            } else {
+                Effect::Disable
+            }
+        } else if before.enabled {
+            Effect::Counted
+        } else {
+            Effect::Skipped
+        }
+    }
+}
+
+impl Markup {
+    fn text(self, text: &[u8], output: &mut String) {
+        let text = String::from_utf8_lossy(text);
+        match self {
+            Markup::Ansi => output.push_str(&text),
+            Markup::Html => {
+                for c in text.chars() {
+                    match c {
+                        '<' => output.push_str("&lt;"),
+                        '>' => output.push_str("&gt;"),
+                        '&' => output.push_str("&amp;"),
+                        '"' => output.push_str("&quot;"),
+                        _ => output.push(c),
+                    }
+                }
+            }
+        }
+    }
+
+    fn call(self, text: &[u8], effect: Effect, sum: usize, output: &mut String) {
+        match self {
+            Markup::Ansi => {
+                let colour = match effect {
+                    Effect::Enable => "\x1b[1;36m",
+                    Effect::Disable => "\x1b[1;31m",
+                    Effect::Counted => "\x1b[1;32m",
+                    Effect::Skipped => "\x1b[2;9m",
+                };
+                output.push_str(colour);
+                self.text(text, output);
+                output.push_str("\x1b[0m");
+                if effect == Effect::Counted {
+                    output.push_str(&format!("\x1b[33m[={}]\x1b[0m", sum));
+                }
+            }
+            Markup::Html => {
+                let class = match effect {
+                    Effect::Enable => "enable",
+                    Effect::Disable => "disable",
+                    Effect::Counted => "counted",
+                    Effect::Skipped => "skipped",
+                };
+                output.push_str(&format!("<span class=\"{}\">", class));
+                self.text(text, output);
+                output.push_str("</span>");
+                if effect == Effect::Counted {
+                    output.push_str(&format!("<sup>={}</sup>", sum));
+                }
+            }
+        }
+    }
+}
+
+const HTML_HEADER: &str = "<style>
+.enable { color: teal; font-weight: bold; }
+.disable { color: red; font-weight: bold; }
+.counted { color: green; font-weight: bold; }
+.skipped { color: gray; text-decoration: line-through; }
+sup { color: goldenrod; }
+</style>
+<pre>";
+
+fn part1(input: &str) -> usize {
+    Interpreter::new(vec![Box::new(Mul)])
+        .run(input.as_bytes())
+        .sum
+}
+
+fn part2(input: &str) -> usize {
+    Interpreter::new(vec![Box::new(Mul), Box::new(Do), Box::new(Dont)])
+        .run(input.as_bytes())
+        .sum
 }
 
 #[cfg(test)]
@@ -64,4 +506,144 @@ mod tests {
     fn test_day3_part2() {
         assert_eq!(part2(TEST_INPUT2), 48);
     }
+
+    #[test]
+    fn test_day3_positions() {
+        let interpreter = Interpreter::new(vec![Box::new(Mul), Box::new(Do), Box::new(Dont)]);
+        let calls: Vec<(usize, &str)> = interpreter
+            .parse(TEST_INPUT2.as_bytes())
+            .iter()
+            .map(|call| {
+                (
+                    call.offset,
+                    &TEST_INPUT2[call.offset..call.offset + call.length],
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            calls,
+            [
+                (1, "mul(2,4)"),
+                (20, "don't()"),
+                (28, "mul(5,5)"),
+                (48, "mul(11,8)"),
+                (59, "do()"),
+                (64, "mul(8,5)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_day3_extra_instructions() {
+        let interpreter = Interpreter::new(vec![
+            Box::new(Mul),
+            Box::new(Do),
+            Box::new(Dont),
+            Box::new(Add),
+        ]);
+        let state =
+            interpreter.run(b"add(1,2)mul(3,4)don't()add(100,100)do()xadd(5,6)add(1000,1)mul(2)");
+
+        assert_eq!(
+            state,
+            State {
+                enabled: true,
+                sum: 3 + 12 + 11
+            }
+        );
+    }
+
+    struct Madd;
+
+    impl Instruction for Madd {
+        fn name(&self) -> &'static str {
+            "madd"
+        }
+
+        fn arity(&self) -> usize {
+            2
+        }
+
+        fn execute(&self, arguments: &[usize], state: &mut State) {
+            if state.enabled {
+                state.sum += 100 * (arguments[0] + arguments[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_day3_streaming() {
+        let cases = [
+            (
+                Interpreter::new(vec![Box::new(Mul), Box::new(Do), Box::new(Dont)]),
+                TEST_INPUT2.repeat(10),
+            ),
+            // One name is the end of another, so cutting a word changes its meaning
+            (
+                Interpreter::new(vec![Box::new(Add), Box::new(Madd)]),
+                "xxxxxxxxxxxxxxxmadd(1,2)".repeat(3),
+            ),
+            (
+                Interpreter::new(vec![Box::new(Add), Box::new(Madd), Box::new(Dont)]),
+                "don't()madd(3,4)add(1,1)xmadd(5,6)don'tmadd(7,8)".repeat(4),
+            ),
+            // Words that span several chunks
+            (
+                Interpreter::new(vec![Box::new(Add), Box::new(Madd), Box::new(Dont)]),
+                format!(
+                    "add(1,2){}madd(3,4)don't{}add(5,6)",
+                    "m".repeat(60),
+                    "x".repeat(45)
+                ),
+            ),
+        ];
+
+        for (interpreter, input) in cases {
+            let expected = interpreter.run(input.as_bytes());
+
+            // Every chunk size splits some instruction somewhere
+            for chunk_size in 1..=input.len() {
+                assert_eq!(
+                    interpreter
+                        .run_reader(input.as_bytes(), chunk_size)
+                        .unwrap(),
+                    expected,
+                    "chunk size {} on {}",
+                    chunk_size,
+                    input
+                );
+            }
+        }
+
+        // Only the end of a long word is kept around, so this doesn't go back
+        // over the whole word for every chunk
+        let interpreter = Interpreter::new(vec![Box::new(Add), Box::new(Madd)]);
+        let input = format!("add(1,2){}madd(3,4)", "m".repeat(1 << 20));
+        assert_eq!(
+            interpreter.run_reader(input.as_bytes(), 16).unwrap().sum,
+            3 + 700
+        );
+    }
+
+    #[test]
+    fn test_day3_annotate() {
+        let interpreter = Interpreter::new(vec![Box::new(Mul), Box::new(Do), Box::new(Dont)]);
+        let input = b"xmul(2,4)&don't()_mul(5,5)<do()?mul(8,5))";
+
+        assert_eq!(
+            interpreter.annotate(input, Markup::Ansi),
+            "x\x1b[1;32mmul(2,4)\x1b[0m\x1b[33m[=8]\x1b[0m&\x1b[1;31mdon't()\x1b[0m_\
+             \x1b[2;9mmul(5,5)\x1b[0m<\x1b[1;36mdo()\x1b[0m?\
+             \x1b[1;32mmul(8,5)\x1b[0m\x1b[33m[=48]\x1b[0m)"
+        );
+
+        let html = interpreter.annotate(input, Markup::Html);
+        assert!(html.ends_with(
+            "<pre>x<span class=\"counted\">mul(2,4)</span><sup>=8</sup>&amp;\
+             <span class=\"disable\">don't()</span>_<span class=\"skipped\">mul(5,5)</span>\
+             &lt;<span class=\"enable\">do()</span>?\
+             <span class=\"counted\">mul(8,5)</span><sup>=48</sup>)</pre>"
+        ));
+    }
 }
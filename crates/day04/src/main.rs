@@ -1,11 +1,60 @@
+use std::collections::{HashMap, HashSet};
+
+use unicode_segmentation::UnicodeSegmentation;
+
 fn main() {
     let input = include_str!("input.txt");
 
+    if std::env::args().nth(1).as_deref() == Some("find") {
+        let grid = Grid::new(input);
+        let words: Vec<String> = std::env::args().skip(2).collect();
+        for found in grid.find_words(&Trie::new(&words)) {
+            let (x, y) = found.cells[0];
+            println!(
+                "{} at ({}, {}) going {:?}",
+                words[found.word], x, y, found.direction
+            );
+        }
+
+        for (word, count) in words.iter().zip(grid.count_words(&words)) {
+            println!("{}: {}", word, count);
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("render") {
+        let grid = Grid::new(input);
+        let (words, patterns) = match std::env::args().nth(3).as_deref() {
+            Some("x-mas") => (vec![], grid.find_pattern(&Pattern::new(X_MAS))),
+            _ => (grid.find_word("XMAS"), vec![]),
+        };
+
+        if std::env::args().nth(2).as_deref() == Some("json") {
+            println!("{}", matches_json(&["XMAS"], &words, &patterns));
+            return;
+        }
+
+        let style = match std::env::args().nth(2).as_deref() {
+            Some("dots") => Style::Dots,
+            _ => Style::Ansi,
+        };
+        let cells: HashSet<(usize, usize)> = words
+            .iter()
+            .flat_map(|found| found.cells.iter())
+            .chain(patterns.iter().flat_map(|found| found.cells.iter()))
+            .copied()
+            .collect();
+        print!("{}", grid.render(&cells, style));
+
+        return;
+    }
+
     println!("Part 1: {}", part1(input));
     println!("Part 2: {}", part2(input));
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum CheckDirection {
     Up,
     Down,
@@ -17,158 +66,363 @@ enum CheckDirection {
     DiagonalDownRight,
 }
 
-fn part1(input: &str) -> usize {
-    let mut words = 0;
-    let chars: Vec<_> = input.chars().collect();
-
-    for (y, line) in chars.split(|&c| c == '\n').enumerate() {
-        let width = line.len() + 1; // Add 1 to account for the newline character
-
-        for (x, &c) in line.iter().enumerate() {
-            if c == 'X' {
-                words += check_word(&chars, x, y, width, "MAS", CheckDirection::Up);
-                words += check_word(&chars, x, y, width, "MAS", CheckDirection::Down);
-                words += check_word(&chars, x, y, width, "MAS", CheckDirection::Left);
-                words += check_word(&chars, x, y, width, "MAS", CheckDirection::Right);
-                words += check_word(&chars, x, y, width, "MAS", CheckDirection::DiagonalUpLeft);
-                words += check_word(&chars, x, y, width, "MAS", CheckDirection::DiagonalUpRight);
-                words += check_word(&chars, x, y, width, "MAS", CheckDirection::DiagonalDownLeft);
-                words += check_word(
-                    &chars,
-                    x,
-                    y,
-                    width,
-                    "MAS",
-                    CheckDirection::DiagonalDownRight,
-                );
-            }
+impl CheckDirection {
+    const ALL: [CheckDirection; 8] = [
+        CheckDirection::Up,
+        CheckDirection::Down,
+        CheckDirection::Left,
+        CheckDirection::Right,
+        CheckDirection::DiagonalUpLeft,
+        CheckDirection::DiagonalUpRight,
+        CheckDirection::DiagonalDownLeft,
+        CheckDirection::DiagonalDownRight,
+    ];
+
+    fn offset(self) -> (isize, isize) {
+        match self {
+            CheckDirection::Up => (0, -1),
+            CheckDirection::Down => (0, 1),
+            CheckDirection::Left => (-1, 0),
+            CheckDirection::Right => (1, 0),
+            CheckDirection::DiagonalUpLeft => (-1, -1),
+            CheckDirection::DiagonalUpRight => (1, -1),
+            CheckDirection::DiagonalDownLeft => (-1, 1),
+            CheckDirection::DiagonalDownRight => (1, 1),
         }
     }
+}
 
-    words
+// Each cell holds one grapheme, so letters with combining marks or emoji
+// take up a single cell. Rows can have different lengths.
+struct Grid<'a> {
+    rows: Vec<Vec<&'a str>>,
 }
 
-fn part2(input: &str) -> usize {
-    let mut words = 0;
-    let chars: Vec<_> = input.chars().collect();
-
-    for (y, line) in chars.split(|&c| c == '\n').enumerate() {
-        let width = line.len() + 1; // Add 1 to account for the newline character
-
-        for (x, &c) in line.iter().enumerate() {
-            if c == 'M' {
-                if check_word(&chars, x, y, width, "AS", CheckDirection::DiagonalUpRight) == 1
-                    && line[x + 2] == 'M'
-                    && check_word(
-                        &chars,
-                        x + 2,
-                        y,
-                        width,
-                        "AS",
-                        CheckDirection::DiagonalUpLeft,
-                    ) == 1
+impl<'a> Grid<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            rows: input
+                .lines()
+                .map(|line| line.trim_end_matches('\r').graphemes(true).collect())
+                .collect(),
+        }
+    }
+
+    // Anything outside the grid is `None`, so searches never have to check
+    // bounds themselves
+    fn get(&self, x: isize, y: isize) -> Option<&'a str> {
+        let row = self.rows.get(usize::try_from(y).ok()?)?;
+        row.get(usize::try_from(x).ok()?).copied()
+    }
+
+    fn positions(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.rows
+            .iter()
+            .enumerate()
+            .flat_map(|(y, row)| (0..row.len()).map(move |x| (x, y)))
+    }
+
+    fn find_word(&self, word: &str) -> Vec<WordMatch> {
+        self.find_words(&Trie::new(&[word]))
+    }
+
+    // Follows the trie from every cell in every direction, so all the words
+    // are found in a single pass over the grid
+    fn find_words(&self, trie: &Trie) -> Vec<WordMatch> {
+        let mut found = vec![];
+        for (x, y) in self.positions() {
+            for direction in CheckDirection::ALL {
+                let (dx, dy) = direction.offset();
+                let mut node = 0;
+                let mut cells = vec![];
+
+                while let Some(next) = self
+                    .get(
+                        x as isize + dx * cells.len() as isize,
+                        y as isize + dy * cells.len() as isize,
+                    )
+                    .and_then(|grapheme| trie.nodes[node].children.get(grapheme))
                 {
-                    words += 1;
-                }
+                    node = *next;
+                    cells.push((
+                        (x as isize + dx * cells.len() as isize) as usize,
+                        (y as isize + dy * cells.len() as isize) as usize,
+                    ));
 
-                if check_word(&chars, x, y, width, "AS", CheckDirection::DiagonalDownRight) == 1 {
-                    if line[x + 2] == 'M'
-                        && check_word(
-                            &chars,
-                            x + 2,
-                            y,
-                            width,
-                            "AS",
-                            CheckDirection::DiagonalDownLeft,
-                        ) == 1
-                    {
-                        words += 1;
+                    // A single letter reads the same in every direction
+                    if cells.len() == 1 && direction != CheckDirection::ALL[0] {
+                        continue;
                     }
+                    for &word in &trie.nodes[node].words {
+                        found.push(WordMatch {
+                            word,
+                            direction,
+                            cells: cells.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        found
+    }
 
-                    if chars[(y + 2) * width + x] == 'M'
-                        && check_word(
-                            &chars,
-                            x,
-                            y + 2,
-                            width,
-                            "AS",
-                            CheckDirection::DiagonalUpRight,
-                        ) == 1
-                    {
-                        words += 1;
+    fn count_words<S: AsRef<str>>(&self, words: &[S]) -> Vec<usize> {
+        let mut counts = vec![0; words.len()];
+        for found in self.find_words(&Trie::new(words)) {
+            counts[found.word] += 1;
+        }
+        counts
+    }
+
+    // Letters that aren't in `cells` are dimmed, or replaced by `.` like in
+    // the puzzle's examples
+    fn render(&self, cells: &HashSet<(usize, usize)>, style: Style) -> String {
+        let mut output = String::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, grapheme) in row.iter().enumerate() {
+                match (style, cells.contains(&(x, y))) {
+                    (Style::Ansi, true) => {
+                        output.push_str(&format!("\x1b[1;33m{}\x1b[0m", grapheme))
                     }
+                    (Style::Ansi, false) => output.push_str(&format!("\x1b[2m{}\x1b[0m", grapheme)),
+                    (Style::Dots, true) => output.push_str(grapheme),
+                    (Style::Dots, false) => output.push('.'),
                 }
+            }
+            output.push('\n');
+        }
+
+        output
+    }
+
+    fn find_pattern(&self, pattern: &Pattern) -> Vec<PatternMatch> {
+        let variants = pattern.variants();
 
-                if check_word(&chars, x, y, width, "AS", CheckDirection::DiagonalDownLeft) == 1
-                    && chars[(y + 2) * width + x] == 'M'
-                    && check_word(
-                        &chars,
-                        x,
-                        y + 2,
-                        width,
-                        "AS",
-                        CheckDirection::DiagonalUpLeft,
-                    ) == 1
+        let mut found = vec![];
+        for (x, y) in self.positions() {
+            for variant in &variants {
+                let cells: Vec<(isize, isize)> = variant
+                    .cells
+                    .iter()
+                    .map(|(dx, dy, _)| (x as isize + dx, y as isize + dy))
+                    .collect();
+
+                if cells
+                    .iter()
+                    .zip(&variant.cells)
+                    .all(|(&(x, y), (_, _, grapheme))| self.get(x, y) == Some(grapheme))
                 {
-                    words += 1;
+                    found.push(PatternMatch {
+                        origin: (x, y),
+                        cells: cells
+                            .into_iter()
+                            .map(|(x, y)| (x as usize, y as usize))
+                            .collect(),
+                    });
                 }
             }
         }
+
+        found
     }
+}
+
+#[derive(Debug, Default)]
+struct TrieNode {
+    children: HashMap<String, usize>,
+    // Indices of the words that end here
+    words: Vec<usize>,
+}
+
+// Words split into graphemes, sharing common prefixes
+struct Trie {
+    nodes: Vec<TrieNode>,
+}
+
+impl Trie {
+    fn new<S: AsRef<str>>(words: &[S]) -> Self {
+        let mut nodes = vec![TrieNode::default()];
+        for (i, word) in words.iter().enumerate() {
+            let mut node = 0;
+            for grapheme in word.as_ref().graphemes(true) {
+                node = match nodes[node].children.get(grapheme) {
+                    Some(&child) => child,
+                    None => {
+                        nodes.push(TrieNode::default());
+                        let child = nodes.len() - 1;
+                        nodes[node].children.insert(grapheme.to_string(), child);
+                        child
+                    }
+                };
+            }
+
+            // The empty word isn't anywhere
+            if node != 0 {
+                nodes[node].words.push(i);
+            }
+        }
 
-    words
+        Self { nodes }
+    }
 }
 
-fn check_word(
-    input: &[char],
-    x: usize,
-    y: usize,
-    width: usize,
-    word: &str,
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WordMatch {
+    // Index into the words searched for
+    word: usize,
     direction: CheckDirection,
-) -> usize {
-    let height = input.len() / width;
+    // Position of each letter of the word, first letter first
+    cells: Vec<(usize, usize)>,
+}
 
-    if match direction {
-        CheckDirection::Up if y >= word.len() => word
-            .chars()
-            .enumerate()
-            .all(|(i, c)| input[(y - i - 1) * width + x] == c),
-        CheckDirection::Down if y < height - word.len() => word
-            .chars()
-            .enumerate()
-            .all(|(i, c)| input[(y + i + 1) * width + x] == c),
-        CheckDirection::Left if x >= word.len() => word
-            .chars()
-            .enumerate()
-            .all(|(i, c)| input[y * width + x - i - 1] == c),
-        CheckDirection::Right if x < width - word.len() => word
-            .chars()
-            .enumerate()
-            .all(|(i, c)| input[y * width + x + i + 1] == c),
-        CheckDirection::DiagonalUpLeft if y >= word.len() && x >= word.len() => word
-            .chars()
-            .enumerate()
-            .all(|(i, c)| input[(y - i - 1) * width + x - i - 1] == c),
-        CheckDirection::DiagonalUpRight if y >= word.len() && x < width - word.len() => word
-            .chars()
-            .enumerate()
-            .all(|(i, c)| input[(y - i - 1) * width + x + i + 1] == c),
-        CheckDirection::DiagonalDownLeft if y < height - word.len() && x >= word.len() => word
-            .chars()
-            .enumerate()
-            .all(|(i, c)| input[(y + i + 1) * width + x - i - 1] == c),
-        CheckDirection::DiagonalDownRight if y < height - word.len() && x < width - word.len() => {
-            word.chars()
-                .enumerate()
-                .all(|(i, c)| input[(y + i + 1) * width + x + i + 1] == c)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    Ansi,
+    Dots,
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::with_capacity(text.len() + 2);
+    json.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn json_cells(cells: &[(usize, usize)]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .map(|(x, y)| format!("[{},{}]", x, y))
+        .collect();
+    format!("[{}]", cells.join(","))
+}
+
+fn matches_json<S: AsRef<str>>(
+    words: &[S],
+    found: &[WordMatch],
+    patterns: &[PatternMatch],
+) -> String {
+    let found: Vec<String> = found
+        .iter()
+        .map(|found| {
+            format!(
+                "{{\"word\":{},\"direction\":\"{:?}\",\"cells\":{}}}",
+                json_string(words[found.word].as_ref()),
+                found.direction,
+                json_cells(&found.cells)
+            )
+        })
+        .collect();
+    let patterns: Vec<String> = patterns
+        .iter()
+        .map(|found| {
+            format!(
+                "{{\"origin\":[{},{}],\"cells\":{}}}",
+                found.origin.0,
+                found.origin.1,
+                json_cells(&found.cells)
+            )
+        })
+        .collect();
+
+    format!(
+        "{{\"words\":[{}],\"patterns\":[{}]}}",
+        found.join(","),
+        patterns.join(",")
+    )
+}
+
+// Letters at offsets from the pattern's top left corner, with the cells that
+// can hold anything left out
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pattern {
+    cells: Vec<(isize, isize, String)>,
+}
+
+impl Pattern {
+    // `.` matches any letter
+    fn new(pattern: &str) -> Self {
+        let mut cells = vec![];
+        for (y, line) in pattern.lines().enumerate() {
+            for (x, grapheme) in line.graphemes(true).enumerate() {
+                if grapheme != "." {
+                    cells.push((x as isize, y as isize, grapheme.to_string()));
+                }
+            }
+        }
+
+        Self { cells }.normalized()
+    }
+
+    // Moves the pattern so that its top left corner is at (0, 0), with the
+    // cells in a fixed order so that equal patterns compare equal
+    fn normalized(mut self) -> Self {
+        let min_x = self.cells.iter().map(|&(x, _, _)| x).min().unwrap_or(0);
+        let min_y = self.cells.iter().map(|&(_, y, _)| y).min().unwrap_or(0);
+        for (x, y, _) in &mut self.cells {
+            *x -= min_x;
+            *y -= min_y;
+        }
+        self.cells.sort_by_key(|&(x, y, _)| (y, x));
+        self
+    }
+
+    fn map(&self, f: impl Fn(isize, isize) -> (isize, isize)) -> Self {
+        Self {
+            cells: self
+                .cells
+                .iter()
+                .map(|(x, y, grapheme)| {
+                    let (x, y) = f(*x, *y);
+                    (x, y, grapheme.clone())
+                })
+                .collect(),
         }
-        _ => false,
-    } {
-        1
-    } else {
-        0
+        .normalized()
     }
+
+    // All distinct rotations and reflections of the pattern
+    fn variants(&self) -> Vec<Pattern> {
+        let mut variants: Vec<Pattern> = vec![];
+        for reflected in [self.clone(), self.map(|x, y| (-x, y))] {
+            let mut rotated = reflected;
+            for _ in 0..4 {
+                rotated = rotated.map(|x, y| (-y, x));
+                if !variants.contains(&rotated) {
+                    variants.push(rotated.clone());
+                }
+            }
+        }
+
+        variants
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PatternMatch {
+    // Top left corner of the matched variant
+    origin: (usize, usize),
+    cells: Vec<(usize, usize)>,
+}
+
+fn part1(input: &str) -> usize {
+    Grid::new(input).find_word("XMAS").len()
+}
+
+const X_MAS: &str = "M.S
+.A.
+M.S";
+
+fn part2(input: &str) -> usize {
+    Grid::new(input).find_pattern(&Pattern::new(X_MAS)).len()
 }
 
 #[cfg(test)]
@@ -195,4 +449,136 @@ MXMXAXMASX";
     fn test_day4_part2() {
         assert_eq!(part2(TEST_INPUT), 9);
     }
+
+    #[test]
+    fn test_day4_word_matches() {
+        let grid = Grid::new("..X...\n.SAMX.\n.A..A.\nXMAS.S\n.X....");
+        let found = grid.find_word("XMAS");
+
+        assert_eq!(found.len(), 4);
+        assert!(found.contains(&WordMatch {
+            word: 0,
+            direction: CheckDirection::Left,
+            cells: vec![(4, 1), (3, 1), (2, 1), (1, 1)],
+        }));
+        assert!(found.contains(&WordMatch {
+            word: 0,
+            direction: CheckDirection::DiagonalDownRight,
+            cells: vec![(2, 0), (3, 1), (4, 2), (5, 3)],
+        }));
+        assert_eq!(grid.find_word("Q"), []);
+        assert_eq!(grid.find_word("S").len(), 3);
+    }
+
+    #[test]
+    fn test_day4_pattern_variants() {
+        assert_eq!(Pattern::new(X_MAS).variants().len(), 4);
+        assert_eq!(Pattern::new("A").variants().len(), 1);
+        // An L shape is different in every orientation
+        assert_eq!(Pattern::new("A.\nBC").variants().len(), 8);
+
+        // Matches at the edge of the grid don't look past it
+        let grid = Grid::new("S.S\n.A.\nM.M");
+        assert_eq!(
+            grid.find_pattern(&Pattern::new(X_MAS)),
+            [PatternMatch {
+                origin: (0, 0),
+                cells: vec![(0, 0), (2, 0), (1, 1), (0, 2), (2, 2)],
+            }]
+        );
+        assert_eq!(Grid::new("M.S\n.A").find_pattern(&Pattern::new(X_MAS)), []);
+    }
+
+    #[test]
+    fn test_day4_messy_grid() {
+        let crlf = TEST_INPUT.replace('\n', "\r\n");
+        assert_eq!(part1(&crlf), 18);
+        assert_eq!(part2(&crlf), 9);
+
+        // Ragged rows, with graphemes made of several code points
+        let grid = Grid::new("Xe\u{301}🇳🇴\nXMASe\u{301}\nM\nA🇳🇴\nS");
+        assert_eq!(grid.find_word("XMAS").len(), 2);
+        assert_eq!(grid.find_word("e\u{301}🇳🇴").len(), 1);
+        assert_eq!(grid.find_word("🇳🇴e\u{301}X").len(), 1);
+        assert_eq!(grid.find_word("e").len(), 0);
+    }
+
+    #[test]
+    fn test_day4_word_counts() {
+        let grid = Grid::new(TEST_INPUT);
+        assert_eq!(
+            grid.count_words(&["XMAS", "SAMX", "MAS", "XM", "X", "", "XMASX", "XMAS"]),
+            [18, 18, 38, 52, 19, 0, 5, 18]
+        );
+    }
+
+    #[test]
+    fn test_day4_render() {
+        let grid = Grid::new(TEST_INPUT);
+
+        let cells: HashSet<_> = grid
+            .find_word("XMAS")
+            .into_iter()
+            .flat_map(|found| found.cells)
+            .collect();
+        assert_eq!(
+            grid.render(&cells, Style::Dots),
+            "....XXMAS.
+.SAMXMS...
+...S..A...
+..A.A.MS.X
+XMASAMX.MM
+X.....XA.A
+S.S.S.S.SS
+.A.A.A.A.A
+..M.M.M.MM
+.X.X.XMASX
+"
+        );
+
+        let cells: HashSet<_> = grid
+            .find_pattern(&Pattern::new(X_MAS))
+            .into_iter()
+            .flat_map(|found| found.cells)
+            .collect();
+        assert_eq!(
+            grid.render(&cells, Style::Dots),
+            ".M.S......
+..A..MSMS.
+.M.S.MAA..
+..A.ASMSM.
+.M.S.M....
+..........
+S.S.S.S.S.
+.A.A.A.A..
+M.M.M.M.M.
+..........
+"
+        );
+
+        let grid = Grid::new("XM\nSA");
+        let cells = HashSet::from([(0, 0)]);
+        assert_eq!(
+            grid.render(&cells, Style::Ansi),
+            "\x1b[1;33mX\x1b[0m\x1b[2mM\x1b[0m\n\x1b[2mS\x1b[0m\x1b[2mA\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_day4_json() {
+        let grid = Grid::new("XMAS\nM.S.\nA.A.\nSM.S");
+        let words = ["XMAS", "\"S\""];
+        let found = grid.find_words(&Trie::new(&words));
+        let patterns = grid.find_pattern(&Pattern::new("M.S\n.A."));
+
+        assert_eq!(
+            matches_json(&words, &found, &patterns),
+            "{\"words\":[\
+             {\"word\":\"XMAS\",\"direction\":\"Down\",\"cells\":[[0,0],[0,1],[0,2],[0,3]]},\
+             {\"word\":\"XMAS\",\"direction\":\"Right\",\"cells\":[[0,0],[1,0],[2,0],[3,0]]}],\
+             \"patterns\":[\
+             {\"origin\":[1,2],\"cells\":[[2,2],[1,3],[3,3]]}]}"
+        );
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+    }
 }
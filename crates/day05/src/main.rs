@@ -1,93 +1,495 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
 fn main() {
     let input = include_str!("input.txt");
 
+    if std::env::args().nth(1).as_deref() == Some("report") {
+        let manual = parse_input(input);
+        let order = PageOrder::new(&manual.rules);
+
+        for update in &manual.updates {
+            match order.validate(update) {
+                Ok(validation) => print!("{}", validation),
+                Err(cycle) => println!("{:?}: {}", update, cycle),
+            }
+        }
+
+        return;
+    }
+
     println!("Part 1: {}", part1(input));
     println!("Part 2: {}", part2(input));
 }
 
-fn part1(input: &str) -> usize {
-    let rules = parse_rules(input);
+#[derive(Debug)]
+struct PageOrder {
+    successors: HashMap<usize, Vec<usize>>,
+}
 
-    input
-        .lines()
-        .skip(rules.len() + 1)
-        .filter_map(|line| {
-            let numbers: Vec<usize> = line
-                .split(',')
-                .map(|n| n.parse().unwrap())
-                .collect::<Vec<_>>();
+#[derive(Debug, PartialEq, Eq)]
+struct Violation {
+    left: usize,
+    right: usize,
+    left_position: usize,
+    right_position: usize,
+}
 
-            for (left, right) in &rules {
-                let left_index = numbers.iter().position(|&n| n == *left);
-                let right_index = numbers.iter().position(|&n| n == *right);
+// Moves are listed so they can be made one after the other, each putting the
+// page straight after one that's already where it belongs
+#[derive(Debug, PartialEq, Eq)]
+struct PageMove {
+    page: usize,
+    from: usize,
+    // The page to put it after, or `None` to put it first
+    after: Option<usize>,
+}
 
-                if let (Some(left_index), Some(right_index)) = (left_index, right_index) {
-                    if left_index > right_index {
-                        return None;
-                    }
+#[derive(Debug, PartialEq, Eq)]
+struct Validation {
+    pages: Vec<usize>,
+    violations: Vec<Violation>,
+    moves: Vec<PageMove>,
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let pages: Vec<String> = self.pages.iter().map(|page| page.to_string()).collect();
+        if self.violations.is_empty() {
+            return writeln!(f, "{}: ok", pages.join(","));
+        }
+
+        writeln!(
+            f,
+            "{}: {} rule(s) violated, {} move(s) to fix",
+            pages.join(","),
+            self.violations.len(),
+            self.moves.len()
+        )?;
+        for violation in &self.violations {
+            writeln!(
+                f,
+                "  {}|{} violated: {} is at {}, {} is at {}",
+                violation.left,
+                violation.right,
+                violation.left,
+                violation.left_position,
+                violation.right,
+                violation.right_position
+            )?;
+        }
+        for page_move in &self.moves {
+            match page_move.after {
+                Some(after) => writeln!(
+                    f,
+                    "  move {} (originally at position {}) to just after {}",
+                    page_move.page, page_move.from, after
+                )?,
+                None => writeln!(
+                    f,
+                    "  move {} (originally at position {}) to the front",
+                    page_move.page, page_move.from
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Cycle {
+    rules: Vec<(usize, usize)>,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "rules form a cycle:")?;
+        for (i, (left, right)) in self.rules.iter().enumerate() {
+            let separator = if i == 0 { "" } else { "," };
+            write!(f, "{} {}|{}", separator, left, right)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PageOrder {
+    fn new(rules: &[(usize, usize)]) -> Self {
+        let mut successors: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &(left, right) in rules {
+            successors.entry(left).or_default().push(right);
+        }
+
+        Self { successors }
+    }
+
+    // The rules that apply to this update, as pairs of indices into `pages`
+    fn edges(&self, pages: &[usize]) -> Vec<(usize, usize)> {
+        let positions: HashMap<usize, usize> = pages
+            .iter()
+            .enumerate()
+            .map(|(i, &page)| (page, i))
+            .collect();
+
+        pages
+            .iter()
+            .enumerate()
+            .flat_map(|(i, page)| {
+                self.successors
+                    .get(page)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|right| positions.get(right))
+                    .map(move |&j| (i, j))
+            })
+            .collect()
+    }
+
+    fn violations(&self, pages: &[usize]) -> Vec<Violation> {
+        self.edges(pages)
+            .into_iter()
+            .filter(|(i, j)| i > j)
+            .map(|(i, j)| Violation {
+                left: pages[i],
+                right: pages[j],
+                left_position: i,
+                right_position: j,
+            })
+            .collect()
+    }
+
+    fn is_ordered(&self, pages: &[usize]) -> bool {
+        self.edges(pages).iter().all(|(i, j)| i < j)
+    }
+
+    fn validate(&self, pages: &[usize]) -> Result<Validation, Cycle> {
+        let violations = self.violations(pages);
+        if violations.is_empty() {
+            return Ok(Validation {
+                pages: pages.to_vec(),
+                violations,
+                moves: vec![],
+            });
+        }
+
+        // Make sure there's an order to fix things up to at all
+        self.sort(pages)?;
+
+        let keep = self.pages_to_keep(pages);
+        let mut edges = self.edges(pages);
+        let kept: Vec<usize> = (0..pages.len()).filter(|&i| keep[i]).collect();
+        edges.extend(kept.windows(2).map(|pair| (pair[0], pair[1])));
+
+        // Sort again with the pages that stay pinned in their current order,
+        // otherwise preferring to leave pages where they are
+        let mut successors = vec![vec![]; pages.len()];
+        let mut in_degree = vec![0; pages.len()];
+        for &(i, j) in &edges {
+            successors[i].push(j);
+            in_degree[j] += 1;
+        }
+
+        let mut queue: BinaryHeap<Reverse<usize>> = (0..pages.len())
+            .filter(|&i| in_degree[i] == 0)
+            .map(Reverse)
+            .collect();
+        let mut targets = vec![0; pages.len()];
+        let mut target = 0;
+        while let Some(Reverse(i)) = queue.pop() {
+            targets[i] = target;
+            target += 1;
+            for &j in &successors[i] {
+                in_degree[j] -= 1;
+                if in_degree[j] == 0 {
+                    queue.push(Reverse(j));
                 }
             }
+        }
+
+        let mut fixed = vec![0; pages.len()];
+        for i in 0..pages.len() {
+            fixed[targets[i]] = pages[i];
+        }
+
+        // Going front to back, whatever a page is put after has either stayed
+        // put or already been moved
+        let mut moved: Vec<usize> = (0..pages.len()).filter(|&i| !keep[i]).collect();
+        moved.sort_by_key(|&i| targets[i]);
+        let moves = moved
+            .into_iter()
+            .map(|i| PageMove {
+                page: pages[i],
+                from: i,
+                after: targets[i].checked_sub(1).map(|target| fixed[target]),
+            })
+            .collect();
 
-            // All rules match, get the middle number
-            Some(numbers[numbers.len() / 2])
+        Ok(Validation {
+            pages: pages.to_vec(),
+            violations,
+            moves,
         })
-        .sum()
-}
+    }
 
-fn part2(input: &str) -> usize {
-    let rules = parse_rules(input);
+    // The largest set of pages that can stay where they are. Page `i`
+    // conflicts with a later page `j` if `j` has to come before `i`, directly
+    // or through other pages. Conflicts form a partial order, so the pages to
+    // keep are a maximum antichain, found through a maximum matching and
+    // König's theorem.
+    fn pages_to_keep(&self, pages: &[usize]) -> Vec<bool> {
+        let mut successors = vec![vec![]; pages.len()];
+        for (i, j) in self.edges(pages) {
+            successors[i].push(j);
+        }
 
-    input
-        .lines()
-        .skip(rules.len() + 1)
-        .filter_map(|line| {
-            let mut numbers: Vec<usize> = line
-                .split(',')
-                .map(|n| n.parse().unwrap())
-                .collect::<Vec<_>>();
-
-            let mut any_changes = false;
-            'outer: loop {
-                for &(left, right) in &rules {
-                    let left_index = numbers.iter().position(|&n| n == left);
-                    let right_index = numbers.iter().position(|&n| n == right);
-
-                    if let (Some(left_index), Some(right_index)) = (left_index, right_index) {
-                        if left_index > right_index {
-                            // Move the left number to after the right number
-                            numbers.remove(left_index);
-                            numbers.insert(right_index, left);
-                            any_changes = true;
-                            continue 'outer;
-                        }
+        let reaches: Vec<Vec<bool>> = (0..pages.len())
+            .map(|i| {
+                let mut seen = vec![false; pages.len()];
+                let mut stack = successors[i].clone();
+                while let Some(j) = stack.pop() {
+                    if !seen[j] {
+                        seen[j] = true;
+                        stack.extend(&successors[j]);
                     }
                 }
+                seen
+            })
+            .collect();
 
-                break;
+        let conflicts: Vec<Vec<usize>> = (0..pages.len())
+            .map(|i| (i + 1..pages.len()).filter(|&j| reaches[j][i]).collect())
+            .collect();
+
+        let mut matches = vec![None; pages.len()];
+        let mut matched = vec![false; pages.len()];
+        for (i, is_matched) in matched.iter_mut().enumerate() {
+            let mut visited = vec![false; pages.len()];
+            *is_matched = augment(i, &conflicts, &mut visited, &mut matches);
+        }
+
+        // Alternating paths from unmatched pages on the left side
+        let mut left = vec![false; pages.len()];
+        let mut right = vec![false; pages.len()];
+        let mut stack: Vec<usize> = (0..pages.len()).filter(|&i| !matched[i]).collect();
+        while let Some(i) = stack.pop() {
+            if left[i] {
+                continue;
+            }
+            left[i] = true;
+            for &j in &conflicts[i] {
+                if !right[j] {
+                    right[j] = true;
+                    stack.extend(matches[j]);
+                }
             }
+        }
 
-            if any_changes {
-                Some(numbers[numbers.len() / 2])
-            } else {
-                None
+        (0..pages.len()).map(|i| left[i] && !right[i]).collect()
+    }
+
+    fn sort(&self, pages: &[usize]) -> Result<Vec<usize>, Cycle> {
+        let edges = self.edges(pages);
+        let mut successors = vec![vec![]; pages.len()];
+        let mut in_degree = vec![0; pages.len()];
+        for &(i, j) in &edges {
+            successors[i].push(j);
+            in_degree[j] += 1;
+        }
+
+        let mut queue: VecDeque<usize> = (0..pages.len()).filter(|&i| in_degree[i] == 0).collect();
+        let mut sorted = Vec::with_capacity(pages.len());
+        while let Some(i) = queue.pop_front() {
+            sorted.push(pages[i]);
+            for &j in &successors[i] {
+                in_degree[j] -= 1;
+                if in_degree[j] == 0 {
+                    queue.push_back(j);
+                }
             }
+        }
+
+        if sorted.len() == pages.len() {
+            return Ok(sorted);
+        }
+
+        // Every page that's left still has a predecessor that's also left, so
+        // walking backwards from any of them has to end up going in circles.
+        let mut predecessors = vec![None; pages.len()];
+        for &(i, j) in &edges {
+            if in_degree[i] > 0 && in_degree[j] > 0 {
+                predecessors[j] = Some(i);
+            }
+        }
+
+        let mut seen = vec![false; pages.len()];
+        let mut current = (0..pages.len()).find(|&i| in_degree[i] > 0).unwrap();
+        while !seen[current] {
+            seen[current] = true;
+            current = predecessors[current].unwrap();
+        }
+
+        let start = current;
+        let mut rules = vec![];
+        loop {
+            let previous = predecessors[current].unwrap();
+            rules.push((pages[previous], pages[current]));
+            current = previous;
+            if current == start {
+                break;
+            }
+        }
+        rules.reverse();
+
+        Err(Cycle { rules })
+    }
+}
+
+fn augment(
+    i: usize,
+    conflicts: &[Vec<usize>],
+    visited: &mut [bool],
+    matches: &mut [Option<usize>],
+) -> bool {
+    for &j in &conflicts[i] {
+        if visited[j] {
+            continue;
+        }
+        visited[j] = true;
+
+        if matches[j].is_none_or(|k| augment(k, conflicts, visited, matches)) {
+            matches[j] = Some(i);
+            return true;
+        }
+    }
+
+    false
+}
+
+fn part1(input: &str) -> usize {
+    let manual = parse_input(input);
+    let order = PageOrder::new(&manual.rules);
+
+    manual
+        .updates
+        .iter()
+        .filter(|update| order.is_ordered(update))
+        .map(|update| update[update.len() / 2])
+        .sum()
+}
+
+fn part2(input: &str) -> usize {
+    let manual = parse_input(input);
+    let order = PageOrder::new(&manual.rules);
+
+    manual
+        .updates
+        .iter()
+        .filter(|update| !order.is_ordered(update))
+        .map(|update| {
+            let sorted = order
+                .sort(update)
+                .unwrap_or_else(|cycle| panic!("Can't order update {:?}: {}", update, cycle));
+            sorted[sorted.len() / 2]
         })
         .sum()
 }
 
-fn parse_rules(input: &str) -> Vec<(usize, usize)> {
-    let mut rules: Vec<(usize, usize)> = vec![];
+#[derive(Debug, PartialEq, Eq)]
+struct Manual {
+    rules: Vec<(usize, usize)>,
+    updates: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseError {
+    MalformedRule { line: usize, text: String },
+    MalformedUpdate { line: usize, text: String },
+    DuplicatePage { line: usize, page: usize },
+}
 
-    for line in input.lines() {
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MalformedRule { line, text } => {
+                write!(f, "line {}: malformed rule {:?}", line, text)
+            }
+            ParseError::MalformedUpdate { line, text } => {
+                write!(f, "line {}: malformed update {:?}", line, text)
+            }
+            ParseError::DuplicatePage { line, page } => {
+                write!(f, "line {}: page {} appears more than once", line, page)
+            }
+        }
+    }
+}
+
+fn parse_input(input: &str) -> Manual {
+    parse_manual(input).unwrap_or_else(|error| panic!("Invalid input: {}", error))
+}
+
+// Rules come first, then a blank line, then the updates. Lines are trimmed
+// (which also takes care of CRLF), and blank lines other than the separator
+// and lines starting with `#` are ignored.
+fn parse_manual(input: &str) -> Result<Manual, ParseError> {
+    let mut manual = Manual {
+        rules: vec![],
+        updates: vec![],
+    };
+    let mut in_rules = true;
+
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
         if line.is_empty() {
-            break;
+            in_rules = false;
+            continue;
         }
 
-        let (left, right) = line.split_once('|').unwrap();
-        rules.push((left.parse().unwrap(), right.parse().unwrap()));
+        // Going by the line itself where that's clear, so blank lines around
+        // either section (or a missing section) don't matter. Nothing after the
+        // first update is a rule, though.
+        let is_rule =
+            manual.updates.is_empty() && (line.contains('|') || (in_rules && !line.contains(',')));
+        if is_rule {
+            let rule = line
+                .split_once('|')
+                .and_then(|(left, right)| Some((parse_page(left)?, parse_page(right)?)))
+                .ok_or_else(|| ParseError::MalformedRule {
+                    line: line_number,
+                    text: line.to_string(),
+                })?;
+            manual.rules.push(rule);
+        } else {
+            let update = line
+                .split(',')
+                .map(parse_page)
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| ParseError::MalformedUpdate {
+                    line: line_number,
+                    text: line.to_string(),
+                })?;
+
+            let mut seen = HashSet::new();
+            if let Some(&page) = update.iter().find(|&&page| !seen.insert(page)) {
+                return Err(ParseError::DuplicatePage {
+                    line: line_number,
+                    page,
+                });
+            }
+            manual.updates.push(update);
+        }
     }
 
-    rules
+    Ok(manual)
+}
+
+fn parse_page(page: &str) -> Option<usize> {
+    page.trim().parse().ok()
 }
 
 #[cfg(test)]
@@ -132,4 +534,174 @@ mod tests {
     fn test_day5_part2() {
         assert_eq!(part2(TEST_INPUT), 123);
     }
+
+    #[test]
+    fn test_day5_large_rule_set() {
+        // Every pair of pages has a rule, and the update is a scrambled copy
+        let pages = 1000;
+        let rules: Vec<(usize, usize)> = (0..pages)
+            .flat_map(|left| (left + 1..pages).map(move |right| (left, right)))
+            .collect();
+        let update: Vec<usize> = (0..pages).map(|i| i * 337 % pages).collect();
+
+        let order = PageOrder::new(&rules);
+        assert!(!order.is_ordered(&update));
+        assert_eq!(order.sort(&update), Ok((0..pages).collect()));
+    }
+
+    #[test]
+    fn test_day5_cycle() {
+        let order = PageOrder::new(&[(1, 2), (2, 3), (3, 4), (4, 2), (5, 1)]);
+        let cycle = order.sort(&[5, 4, 3, 2, 1]).unwrap_err();
+
+        assert_eq!(cycle.rules, [(4, 2), (2, 3), (3, 4)]);
+        assert_eq!(cycle.to_string(), "rules form a cycle: 4|2, 2|3, 3|4");
+    }
+
+    #[test]
+    fn test_day5_validate() {
+        let manual = parse_input(TEST_INPUT);
+        let order = PageOrder::new(&manual.rules);
+
+        assert!(order
+            .validate(&[75, 47, 61, 53, 29])
+            .unwrap()
+            .moves
+            .is_empty());
+
+        let validation = order.validate(&[97, 13, 75, 29, 47]).unwrap();
+        assert_eq!(validation.violations.len(), 4);
+        assert_eq!(
+            validation.moves,
+            [
+                PageMove {
+                    page: 29,
+                    from: 3,
+                    after: Some(47)
+                },
+                PageMove {
+                    page: 13,
+                    from: 1,
+                    after: Some(29)
+                },
+            ]
+        );
+        assert_eq!(
+            validation.to_string().lines().skip(5).collect::<Vec<_>>(),
+            [
+                "  move 29 (originally at position 3) to just after 47",
+                "  move 13 (originally at position 1) to just after 29"
+            ]
+        );
+
+        // Following the moves one by one fixes every update
+        for update in &manual.updates {
+            let mut pages = update.clone();
+            for page_move in order.validate(update).unwrap().moves {
+                pages.retain(|&page| page != page_move.page);
+                let position = page_move.after.map_or(0, |after| {
+                    pages.iter().position(|&page| page == after).unwrap() + 1
+                });
+                pages.insert(position, page_move.page);
+            }
+            assert!(order.is_ordered(&pages), "{:?}", update);
+        }
+
+        let validation = order.validate(&[75, 97, 47, 61, 53]).unwrap();
+        assert_eq!(
+            validation.violations,
+            [Violation {
+                left: 97,
+                right: 75,
+                left_position: 1,
+                right_position: 0
+            }]
+        );
+        assert_eq!(
+            validation.moves,
+            [PageMove {
+                page: 75,
+                from: 0,
+                after: Some(97)
+            }]
+        );
+        assert_eq!(
+            order.validate(&[29, 13, 97]).unwrap().moves,
+            [PageMove {
+                page: 97,
+                from: 2,
+                after: None
+            }]
+        );
+    }
+
+    #[test]
+    fn test_day5_parse_messy_input() {
+        let input = format!(
+            "# page ordering rules\r\n{}\r\n  \r\n\r\n",
+            TEST_INPUT
+                .lines()
+                .map(|line| format!(" {} ", line))
+                .collect::<Vec<_>>()
+                .join("\r\n")
+        );
+
+        assert_eq!(parse_manual(&input), parse_manual(TEST_INPUT));
+        assert_eq!(part1(&input), 143);
+        assert_eq!(part2(&input), 123);
+    }
+
+    #[test]
+    fn test_day5_parse_errors() {
+        assert_eq!(
+            parse_manual("47|53\n47-61\n\n75,47"),
+            Err(ParseError::MalformedRule {
+                line: 2,
+                text: "47-61".to_string()
+            })
+        );
+        assert_eq!(
+            parse_manual("47|53\n\n75,47\n75,,47"),
+            Err(ParseError::MalformedUpdate {
+                line: 4,
+                text: "75,,47".to_string()
+            })
+        );
+        assert_eq!(
+            parse_manual("47|53\n\n75,47,61,47"),
+            Err(ParseError::DuplicatePage { line: 3, page: 47 })
+        );
+        assert_eq!(
+            parse_manual("47|53\n\n75,47\n61|13"),
+            Err(ParseError::MalformedUpdate {
+                line: 4,
+                text: "61|13".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_day5_parse_missing_sections() {
+        assert_eq!(
+            parse_manual("\n75,47,61"),
+            Ok(Manual {
+                rules: vec![],
+                updates: vec![vec![75, 47, 61]]
+            })
+        );
+        assert_eq!(
+            parse_manual("\n\n47|53\n\n75"),
+            Ok(Manual {
+                rules: vec![(47, 53)],
+                updates: vec![vec![75]]
+            })
+        );
+        assert_eq!(
+            parse_manual("47|53\n97|13\n"),
+            Ok(Manual {
+                rules: vec![(47, 53), (97, 13)],
+                updates: vec![]
+            })
+        );
+    }
 }
@@ -4,18 +4,294 @@ use std::collections::HashSet;
 fn main() {
     let input = include_str!("input.txt");
 
-    let positions_visited = part1(input);
-    println!("Part 1: {}", positions_visited.len());
-    println!("Part 2: {}", part2(input, positions_visited));
+    if std::env::args().nth(1).as_deref() == Some("guards") {
+        let turning = match std::env::args().nth(2).as_deref() {
+            None | Some("right") => Turning::Right,
+            Some("left") => Turning::Left,
+            Some("reverse") => Turning::Reverse,
+            Some("random") => Turning::Random {
+                state: std::env::args()
+                    .nth(3)
+                    .map_or(0, |seed| seed.parse().unwrap()),
+            },
+            Some(rule) => panic!("Unknown turning rule: {}", rule),
+        };
+
+        for (i, coverage) in parse_input(input)
+            .simulate(turning, 1_000_000)
+            .iter()
+            .enumerate()
+        {
+            let start = &coverage.start;
+            print!(
+                "Guard {} from ({}, {}) facing {:?}: {} positions, {} steps, {} turns, ",
+                i,
+                start.x,
+                start.y,
+                start.direction,
+                coverage.positions,
+                coverage.steps,
+                coverage.turns
+            );
+            match coverage.left_at {
+                Some(tick) => println!("left on tick {}", tick),
+                None => println!("still patrolling"),
+            }
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("edges") {
+        let edges = match std::env::args().nth(2).as_deref() {
+            None | Some("exit") => Edges::Exit,
+            Some("wrap") => Edges::Wrap,
+            Some("reflect") => Edges::Reflect,
+            Some(edges) => panic!("Unknown edge rule: {}", edges),
+        };
+
+        let field = parse_input(input);
+        let walk = field.walk(&field.grid, edges);
+        println!("Positions covered: {}", distinct_positions(&walk.states));
+        match (walk.cycle_start, walk.cycle()) {
+            (Some(cycle_start), Some(cycle)) => println!(
+                "Cycle from step {} with period {}, covering {} positions",
+                cycle_start,
+                cycle.len(),
+                distinct_positions(cycle)
+            ),
+            _ => println!("Left the map after {} steps", walk.states.len()),
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("loops") {
+        println!("{}", loops_json(&parse_input(input).loops()));
+        return;
+    }
+
+    println!("Part 1: {}", part1(input));
+    println!("Part 2: {}", part2(input));
 }
 
 #[derive(Debug)]
 struct Field {
     grid: Vec<bool>,
-    guard: Guard,
+    width: usize,
+    height: usize,
+    // In the order they appear on the map
+    guards: Vec<Guard>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl Field {
+    // The puzzle has a single guard, and everything but `simulate` only
+    // follows one. Picking one of several would quietly change the answers.
+    fn guard(&self) -> &Guard {
+        assert_eq!(
+            self.guards.len(),
+            1,
+            "The map has {} guards, but only the guards mode handles more than one",
+            self.guards.len()
+        );
+        &self.guards[0]
+    }
+
+    fn contains(&self, guard: &Guard) -> bool {
+        guard.x >= 0
+            && (guard.x as usize) < self.width
+            && guard.y >= 0
+            && (guard.y as usize) < self.height
+    }
+
+    fn index(&self, guard: &Guard) -> usize {
+        guard.y as usize * self.width + guard.x as usize
+    }
+
+    // Every position the guard visits before leaving the map (or going round
+    // in circles), in the order they're first visited, along with where the
+    // guard was just before it first stepped there (nowhere for the starting
+    // position)
+    fn patrol(&self) -> Vec<(usize, Option<Guard>)> {
+        let mut guard = self.guard().clone();
+        let mut seen = vec![false; self.grid.len()];
+        let mut states = Visited::new(self.grid.len() * 4);
+        let mut visited = vec![(self.index(&guard), None)];
+        seen[self.index(&guard)] = true;
+
+        while states.insert(self.index(&guard) * 4 + guard.direction.index()) {
+            if guard.has_block_in_front(&self.grid, self.width, self.height) {
+                guard.turn_right();
+                continue;
+            }
+
+            let before = guard.clone();
+            guard.step_forward();
+            if !self.contains(&guard) {
+                break;
+            }
+
+            let i = self.index(&guard);
+            if !seen[i] {
+                seen[i] = true;
+                visited.push((i, Some(before)));
+            }
+        }
+
+        visited
+    }
+
+    // Every tick, each guard still on the map either turns or steps forward.
+    // Other guards block the way like obstacles do, going by where they were
+    // at the start of the tick. Guards move in order, so when two would step
+    // onto the same position, the later one finds it taken. Guards that never
+    // leave are stopped after `max_ticks`.
+    fn simulate(&self, mut turning: Turning, max_ticks: usize) -> Vec<Coverage> {
+        let mut guards: Vec<Option<Guard>> = self.guards.iter().cloned().map(Some).collect();
+        let mut seen = vec![vec![false; self.grid.len()]; guards.len()];
+        let mut coverage: Vec<Coverage> = self
+            .guards
+            .iter()
+            .map(|guard| Coverage {
+                start: guard.clone(),
+                positions: 1,
+                steps: 0,
+                turns: 0,
+                left_at: None,
+            })
+            .collect();
+        for (i, guard) in self.guards.iter().enumerate() {
+            seen[i][self.index(guard)] = true;
+        }
+
+        for tick in 1..=max_ticks {
+            if guards.iter().all(Option::is_none) {
+                break;
+            }
+
+            let mut occupied: Vec<(isize, isize)> = guards
+                .iter()
+                .flatten()
+                .map(|guard| (guard.x, guard.y))
+                .collect();
+
+            for (i, slot) in guards.iter_mut().enumerate() {
+                let Some(guard) = slot else {
+                    continue;
+                };
+
+                if guard.has_block_in_front(&self.grid, self.width, self.height)
+                    || occupied.contains(&guard.position_in_front())
+                {
+                    turning.turn(guard);
+                    coverage[i].turns += 1;
+                    continue;
+                }
+
+                guard.step_forward();
+                occupied.push((guard.x, guard.y));
+                coverage[i].steps += 1;
+                if !self.contains(guard) {
+                    coverage[i].left_at = Some(tick);
+                    *slot = None;
+                    continue;
+                }
+
+                let position = self.index(guard);
+                if !seen[i][position] {
+                    seen[i][position] = true;
+                    coverage[i].positions += 1;
+                }
+            }
+        }
+
+        coverage
+    }
+
+    // Turns or steps forward, returning false if the guard walked off the map
+    fn advance(&self, guard: &mut Guard, grid: &[bool], edges: Edges) -> bool {
+        let (mut x, mut y) = guard.position_in_front();
+        if !(0..self.width as isize).contains(&x) || !(0..self.height as isize).contains(&y) {
+            match edges {
+                Edges::Exit => return false,
+                Edges::Wrap => {
+                    x = x.rem_euclid(self.width as isize);
+                    y = y.rem_euclid(self.height as isize);
+                }
+                Edges::Reflect => {
+                    guard.turn_around();
+                    return true;
+                }
+            }
+        }
+
+        if grid[y as usize * self.width + x as usize] {
+            guard.turn_right();
+        } else {
+            guard.x = x;
+            guard.y = y;
+        }
+        true
+    }
+
+    // Walks step by step, since every state in a cycle can be needed. Turning
+    // on the spot counts as a step.
+    fn walk(&self, grid: &[bool], edges: Edges) -> Walk {
+        let mut guard = self.guard().clone();
+        let mut states = vec![];
+        let mut first_step = vec![None; grid.len() * 4];
+
+        loop {
+            let state = self.index(&guard) * 4 + guard.direction.index();
+            if let Some(cycle_start) = first_step[state] {
+                return Walk {
+                    states,
+                    cycle_start: Some(cycle_start),
+                };
+            }
+            first_step[state] = Some(states.len());
+            states.push(guard.clone());
+
+            if !self.advance(&mut guard, grid, edges) {
+                return Walk {
+                    states,
+                    cycle_start: None,
+                };
+            }
+        }
+    }
+
+    fn loop_with_obstruction(&self, obstruction: usize) -> Option<Loop> {
+        let mut grid = self.grid.clone();
+        grid[obstruction] = true;
+
+        let mut walk = self.walk(&grid, Edges::Exit);
+        let entry_step = walk.cycle_start?;
+        Some(Loop {
+            obstruction: (
+                (obstruction % self.width) as isize,
+                (obstruction / self.width) as isize,
+            ),
+            entry_step,
+            cycle: walk.states.split_off(entry_step),
+        })
+    }
+
+    fn loops(&self) -> Vec<Loop> {
+        let mut obstructions = looping_obstructions(self);
+        obstructions.sort_unstable();
+
+        obstructions
+            .par_iter()
+            .map(|&i| {
+                self.loop_with_obstruction(i)
+                    .expect("Obstruction should cause a loop")
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 struct Guard {
     x: isize,
     y: isize,
@@ -45,6 +321,29 @@ impl Guard {
         };
     }
 
+    fn turn_left(&mut self) {
+        self.direction = match self.direction {
+            Direction::North => Direction::West,
+            Direction::East => Direction::North,
+            Direction::South => Direction::East,
+            Direction::West => Direction::South,
+        };
+    }
+
+    fn turn_around(&mut self) {
+        self.direction = match self.direction {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        };
+    }
+
+    fn position_in_front(&self) -> (isize, isize) {
+        let (dx, dy) = self.direction.offset();
+        (self.x + dx, self.y + dy)
+    }
+
     fn step_forward(&mut self) {
         match self.direction {
             Direction::North => self.y -= 1,
@@ -63,70 +362,318 @@ enum Direction {
     West,
 }
 
-fn part1(input: &str) -> HashSet<(isize, isize)> {
-    let Field { grid, mut guard } = parse_input(input);
-    let width = input.lines().next().unwrap().len();
-    let height = input.lines().count();
-    let mut unique_positions = HashSet::new();
+impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::North,
+        Direction::East,
+        Direction::South,
+        Direction::West,
+    ];
 
-    loop {
-        unique_positions.insert((guard.x, guard.y));
+    fn index(self) -> usize {
+        self as usize
+    }
 
-        if guard.has_block_in_front(&grid, width, height) {
-            guard.turn_right()
-        } else {
-            guard.step_forward()
+    fn offset(self) -> (isize, isize) {
+        match self {
+            Direction::North => (0, -1),
+            Direction::East => (1, 0),
+            Direction::South => (0, 1),
+            Direction::West => (-1, 0),
         }
+    }
 
-        if guard.y < 0 || guard.y as usize >= height || guard.x < 0 || guard.x as usize >= width {
-            break;
-        }
+    fn turned_right(self) -> Self {
+        Direction::ALL[(self.index() + 1) % 4]
     }
+}
 
-    unique_positions
+const NO_STOP: u32 = u32::MAX;
+
+// For every position and direction, where the guard ends up when walking
+// that way until the next obstacle, so a whole straight stretch of the patrol
+// takes a single lookup
+struct JumpTable {
+    width: usize,
+    // `NO_STOP` if the guard walks off the map instead
+    stops: Vec<[u32; 4]>,
 }
 
-fn part2(input: &str, positions_ever_visited: HashSet<(isize, isize)>) -> usize {
-    let Field { grid, guard } = parse_input(input);
-    let width = input.lines().next().unwrap().len();
-    let height = input.lines().count();
+impl JumpTable {
+    fn new(field: &Field) -> Self {
+        let (width, height) = (field.width, field.height);
+        let mut stops = vec![[NO_STOP; 4]; field.grid.len()];
 
-    positions_ever_visited
-        .par_iter()
-        .filter(|(x, y)| guard.x != *x || guard.y != *y)
-        .map(|(x, y)| (y * width as isize + x) as usize)
-        .filter(|i| {
-            let mut grid = grid.clone();
-            grid[*i] = true;
-            let mut guard = guard.clone();
-
-            let mut unique_positions = HashSet::new();
-            loop {
-                unique_positions.insert((guard.x, guard.y, guard.direction));
-
-                if guard.has_block_in_front(&grid, width, height) {
-                    guard.turn_right()
-                } else {
-                    guard.step_forward()
+        for direction in Direction::ALL {
+            let (dx, dy) = direction.offset();
+            // Walk each row or column against the direction, so the stop for
+            // each position is either just in front of the next obstacle or
+            // the same as for the position ahead of it
+            let lines: Vec<Vec<usize>> = if dx != 0 {
+                (0..height)
+                    .map(|y| (0..width).map(|x| y * width + x).collect())
+                    .collect()
+            } else {
+                (0..width)
+                    .map(|x| (0..height).map(|y| y * width + x).collect())
+                    .collect()
+            };
+
+            for mut line in lines {
+                if dx > 0 || dy > 0 {
+                    line.reverse();
                 }
 
-                if guard.y < 0
-                    || guard.y as usize >= height
-                    || guard.x < 0
-                    || guard.x as usize >= width
-                {
-                    break false;
-                } else if unique_positions.contains(&(guard.x, guard.y, guard.direction)) {
-                    break true;
+                let mut stop = NO_STOP;
+                let mut blocked_ahead = false;
+                for i in line {
+                    if field.grid[i] {
+                        blocked_ahead = true;
+                        continue;
+                    }
+                    if blocked_ahead {
+                        stop = i as u32;
+                        blocked_ahead = false;
+                    }
+                    stops[i][direction.index()] = stop;
                 }
             }
+        }
+
+        Self { width, stops }
+    }
+
+    // Where the guard stops, taking an extra obstruction into account
+    fn jump(&self, position: usize, direction: Direction, obstruction: usize) -> Option<usize> {
+        let stop = self.stops[position][direction.index()];
+        let (x, y) = (
+            (position % self.width) as isize,
+            (position / self.width) as isize,
+        );
+        let (ox, oy) = (
+            (obstruction % self.width) as isize,
+            (obstruction / self.width) as isize,
+        );
+        let (dx, dy) = direction.offset();
+
+        // How far ahead the obstruction is, if it's ahead at all
+        let ahead = if dx == 0 && ox == x {
+            (oy - y) * dy
+        } else if dy == 0 && oy == y {
+            (ox - x) * dx
+        } else {
+            0
+        };
+        let stop_distance = if stop == NO_STOP {
+            isize::MAX
+        } else {
+            let stop = stop as usize;
+            ((stop % self.width) as isize - x) * dx + ((stop / self.width) as isize - y) * dy
+        };
+
+        if ahead > 0 && ahead <= stop_distance {
+            let distance = ahead - 1;
+            Some(((y + dy * distance) * self.width as isize + x + dx * distance) as usize)
+        } else if stop == NO_STOP {
+            None
+        } else {
+            Some(stop as usize)
+        }
+    }
+
+    // Follows the patrol from `guard` one straight stretch at a time, with an
+    // extra obstruction placed on the map
+    fn loops(&self, guard: &Guard, obstruction: usize, visited: &mut Visited) -> bool {
+        let mut position = guard.y as usize * self.width + guard.x as usize;
+        let mut direction = guard.direction;
+
+        let looped = loop {
+            let Some(stop) = self.jump(position, direction, obstruction) else {
+                break false;
+            };
+            position = stop;
+            direction = direction.turned_right();
+
+            if !visited.insert(position * 4 + direction.index()) {
+                break true;
+            }
+        };
+
+        visited.clear();
+        looped
+    }
+}
+
+// A bitset that remembers which bits it set, so clearing it costs as much as
+// the patrol rather than the whole map
+struct Visited {
+    bits: Vec<u64>,
+    touched: Vec<usize>,
+}
+
+impl Visited {
+    fn new(size: usize) -> Self {
+        Self {
+            bits: vec![0; size.div_ceil(64)],
+            touched: vec![],
+        }
+    }
+
+    // Returns whether the bit wasn't set yet
+    fn insert(&mut self, bit: usize) -> bool {
+        let (word, mask) = (bit / 64, 1 << (bit % 64));
+        if self.bits[word] & mask != 0 {
+            return false;
+        }
+        self.bits[word] |= mask;
+        self.touched.push(word);
+        true
+    }
+
+    fn clear(&mut self) {
+        for word in self.touched.drain(..) {
+            self.bits[word] = 0;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Turning {
+    Right,
+    Left,
+    Reverse,
+    // Any of the other three directions, picked with a seeded generator so
+    // runs can be repeated
+    Random { state: u64 },
+}
+
+impl Turning {
+    fn turn(&mut self, guard: &mut Guard) {
+        match self {
+            Turning::Right => guard.turn_right(),
+            Turning::Left => guard.turn_left(),
+            Turning::Reverse => guard.turn_around(),
+            Turning::Random { state } => {
+                *state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                match (*state >> 33) % 3 {
+                    0 => guard.turn_right(),
+                    1 => guard.turn_left(),
+                    _ => guard.turn_around(),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Coverage {
+    start: Guard,
+    positions: usize,
+    steps: usize,
+    turns: usize,
+    // The tick on which the guard walked off the map, if it did
+    left_at: Option<usize>,
+}
+
+fn part1(input: &str) -> usize {
+    parse_input(input).patrol().len()
+}
+
+// Putting an obstruction somewhere the guard never goes changes nothing, and
+// the patrol is the same as before up to the point the guard first walks into
+// it, so each simulation starts from there
+fn looping_obstructions(field: &Field) -> Vec<usize> {
+    let table = JumpTable::new(field);
+
+    field
+        .patrol()
+        .par_iter()
+        .filter_map(|(i, before)| Some((*i, before.as_ref()?)))
+        .map_init(
+            || Visited::new(field.grid.len() * 4),
+            |visited, (i, before)| table.loops(before, i, visited).then_some(i),
+        )
+        .flatten()
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Loop {
+    obstruction: (isize, isize),
+    // How many steps the guard takes before first reaching a state in the
+    // cycle. Turning on the spot counts as a step.
+    entry_step: usize,
+    // Every state the guard goes through once it's in the loop, in order
+    cycle: Vec<Guard>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edges {
+    // The puzzle's rule: walking off the map ends the patrol
+    Exit,
+    // Walking off one side comes back in on the opposite side
+    Wrap,
+    // The guard turns around instead of walking off
+    Reflect,
+}
+
+// Every state of a patrol until the guard left the map or got back to a state
+// it was in before
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Walk {
+    states: Vec<Guard>,
+    // Where in `states` the cycle starts, if there is one
+    cycle_start: Option<usize>,
+}
+
+impl Walk {
+    fn cycle(&self) -> Option<&[Guard]> {
+        Some(&self.states[self.cycle_start?..])
+    }
+}
+
+fn distinct_positions(states: &[Guard]) -> usize {
+    states
+        .iter()
+        .map(|guard| (guard.x, guard.y))
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+fn loops_json(loops: &[Loop]) -> String {
+    let loops: Vec<String> = loops
+        .iter()
+        .map(|found| {
+            let cycle: Vec<String> = found
+                .cycle
+                .iter()
+                .map(|guard| format!("[{},{},\"{:?}\"]", guard.x, guard.y, guard.direction))
+                .collect();
+            format!(
+                "{{\"obstruction\":[{},{}],\"entry_step\":{},\"length\":{},\"cycle\":[{}]}}",
+                found.obstruction.0,
+                found.obstruction.1,
+                found.entry_step,
+                found.cycle.len(),
+                cycle.join(",")
+            )
         })
-        .count()
+        .collect();
+
+    format!("[{}]", loops.join(","))
+}
+
+fn part2(input: &str) -> usize {
+    looping_obstructions(&parse_input(input)).len()
 }
 
 fn parse_input(input: &str) -> Field {
     let mut grid = Vec::new();
-    let mut guard = None;
+    let mut guards = Vec::new();
+    let width = input.lines().next().unwrap().len();
+    let height = input.lines().count();
 
     for (y, line) in input.lines().enumerate() {
         for (x, c) in line.chars().enumerate() {
@@ -134,7 +681,7 @@ fn parse_input(input: &str) -> Field {
                 '.' => grid.push(false),
                 '#' => grid.push(true),
                 '^' => {
-                    guard = Some(Guard {
+                    guards.push(Guard {
                         x: x as isize,
                         y: y as isize,
                         direction: Direction::North,
@@ -142,7 +689,7 @@ fn parse_input(input: &str) -> Field {
                     grid.push(false);
                 }
                 '>' => {
-                    guard = Some(Guard {
+                    guards.push(Guard {
                         x: x as isize,
                         y: y as isize,
                         direction: Direction::East,
@@ -150,7 +697,7 @@ fn parse_input(input: &str) -> Field {
                     grid.push(false);
                 }
                 'v' => {
-                    guard = Some(Guard {
+                    guards.push(Guard {
                         x: x as isize,
                         y: y as isize,
                         direction: Direction::South,
@@ -158,7 +705,7 @@ fn parse_input(input: &str) -> Field {
                     grid.push(false);
                 }
                 '<' => {
-                    guard = Some(Guard {
+                    guards.push(Guard {
                         x: x as isize,
                         y: y as isize,
                         direction: Direction::West,
@@ -170,9 +717,12 @@ fn parse_input(input: &str) -> Field {
         }
     }
 
+    assert!(!guards.is_empty(), "No guard on the map");
     Field {
         grid,
-        guard: guard.unwrap(),
+        width,
+        height,
+        guards,
     }
 }
 
@@ -193,12 +743,199 @@ mod tests {
 
     #[test]
     fn test_day6_part1() {
-        assert_eq!(part1(TEST_INPUT).len(), 41);
+        assert_eq!(part1(TEST_INPUT), 41);
     }
 
     #[test]
     fn test_day6_part2() {
-        let positions_ever_visited = part1(TEST_INPUT);
-        assert_eq!(part2(TEST_INPUT, positions_ever_visited), 6);
+        assert_eq!(part2(TEST_INPUT), 6);
+    }
+
+    // The straightforward step by step simulation, to check the jump table
+    fn loops_step_by_step(field: &Field, obstruction: Option<usize>) -> bool {
+        let mut grid = field.grid.clone();
+        if let Some(obstruction) = obstruction {
+            grid[obstruction] = true;
+        }
+        let mut guard = field.guard().clone();
+        let mut seen = HashSet::new();
+
+        while seen.insert(guard.clone()) {
+            if guard.has_block_in_front(&grid, field.width, field.height) {
+                guard.turn_right();
+            } else {
+                guard.step_forward();
+            }
+            if !field.contains(&guard) {
+                return false;
+            }
+        }
+        true
+    }
+
+    #[test]
+    fn test_day6_jump_table() {
+        // Pseudo-random maps, with the guard somewhere in the middle
+        let mut seed = 7u64;
+        for _ in 0..20 {
+            let mut input = String::new();
+            for y in 0..15 {
+                for x in 0..17 {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    input.push(match (x, y) {
+                        (8, 7) => '^',
+                        _ if seed >> 60 < 2 => '#',
+                        _ => '.',
+                    });
+                }
+                input.push('\n');
+            }
+
+            let field = parse_input(&input);
+            if loops_step_by_step(&field, None) {
+                continue;
+            }
+            let expected = (0..field.grid.len())
+                .filter(|&i| !field.grid[i] && i != field.index(field.guard()))
+                .filter(|&i| loops_step_by_step(&field, Some(i)))
+                .count();
+            assert_eq!(part2(&input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_day6_loops() {
+        let loops = parse_input(TEST_INPUT).loops();
+        assert_eq!(
+            loops
+                .iter()
+                .map(|found| found.obstruction)
+                .collect::<Vec<_>>(),
+            [(3, 6), (6, 7), (7, 7), (1, 8), (3, 8), (7, 9)]
+        );
+
+        // The first option from the puzzle: the guard walks a rectangle
+        // between (4, 1) and (8, 6) right from the start, turning four times
+        let found = &loops[0];
+        assert_eq!(found.entry_step, 0);
+        assert_eq!(found.cycle.len(), 2 * (5 + 4) + 4);
+        assert_eq!(
+            found.cycle[0],
+            Guard {
+                x: 4,
+                y: 6,
+                direction: Direction::North
+            }
+        );
+
+        let json = loops_json(&loops[..1]);
+        assert!(json.starts_with(
+            "[{\"obstruction\":[3,6],\"entry_step\":0,\"length\":22,\"cycle\":[[4,6,\"North\"],[4,5,\"North\"],"
+        ));
+        assert!(json.ends_with("[5,6,\"West\"],[4,6,\"West\"]]}]"));
+
+        // Here the guard walks up, right and down first, and only starts
+        // going round once heading west past (7, 6)
+        let found = &loops[1];
+        assert_eq!(found.obstruction, (6, 7));
+        assert_eq!(found.entry_step, 5 + 1 + 4 + 1 + 5 + 1 + 2);
+        assert_eq!(found.cycle.len(), 16);
+        assert_eq!(
+            found.cycle[0],
+            Guard {
+                x: 6,
+                y: 6,
+                direction: Direction::West
+            }
+        );
+    }
+
+    #[test]
+    fn test_day6_guards() {
+        let coverage = parse_input(TEST_INPUT).simulate(Turning::Right, 10_000);
+        assert_eq!(coverage.len(), 1);
+        assert_eq!(coverage[0].positions, 41);
+        assert_eq!(
+            coverage[0].left_at,
+            Some(coverage[0].steps + coverage[0].turns)
+        );
+
+        // Turning around leads straight back out
+        let coverage = parse_input(TEST_INPUT).simulate(Turning::Reverse, 10_000);
+        assert_eq!(coverage[0].positions, 9);
+        assert_eq!(coverage[0].turns, 1);
+
+        // Two guards facing each other both turn right, then walk off
+        let field = parse_input("....\n.><.\n....");
+        assert_eq!(field.guards.len(), 2);
+        let coverage = field.simulate(Turning::Right, 100);
+        assert_eq!(
+            coverage
+                .iter()
+                .map(|coverage| (coverage.positions, coverage.turns, coverage.left_at))
+                .collect::<Vec<_>>(),
+            [(2, 1, Some(3)), (2, 1, Some(3))]
+        );
+
+        // Only the first guard gets the position between them
+        let coverage = parse_input(">.<").simulate(Turning::Right, 100);
+        assert_eq!(
+            coverage
+                .iter()
+                .map(|coverage| (coverage.positions, coverage.turns, coverage.left_at))
+                .collect::<Vec<_>>(),
+            [(2, 1, Some(3)), (1, 1, Some(2))]
+        );
+
+        let field = parse_input("#####\n#...#\n#.^.#\n#...#\n#####");
+        let coverage = field.simulate(Turning::Random { state: 1 }, 100);
+        assert_eq!(coverage, field.simulate(Turning::Random { state: 1 }, 100));
+        // Walled in, so it never leaves
+        assert_eq!(coverage[0].left_at, None);
+        assert_eq!(coverage[0].steps + coverage[0].turns, 100);
+    }
+
+    #[test]
+    fn test_day6_edges() {
+        let field = parse_input(TEST_INPUT);
+        let walk = field.walk(&field.grid, Edges::Exit);
+        assert_eq!(walk.cycle_start, None);
+        assert_eq!(distinct_positions(&walk.states), 41);
+
+        // Without an exit, every patrol ends up going round
+        for edges in [Edges::Wrap, Edges::Reflect] {
+            let walk = field.walk(&field.grid, edges);
+            let cycle = walk.cycle().unwrap();
+            let mut guard = cycle[cycle.len() - 1].clone();
+            assert!(field.advance(&mut guard, &field.grid, edges));
+            assert_eq!(guard, cycle[0]);
+        }
+
+        let field = parse_input("...\n.^.\n...");
+        let walk = field.walk(&field.grid, Edges::Wrap);
+        assert_eq!(walk.cycle_start, Some(0));
+        assert_eq!(walk.cycle().unwrap().len(), 3);
+
+        // Up, turn around, down, turn around and back to the middle
+        let walk = field.walk(&field.grid, Edges::Reflect);
+        assert_eq!(walk.cycle_start, Some(0));
+        assert_eq!(walk.cycle().unwrap().len(), 6);
+        assert_eq!(distinct_positions(walk.cycle().unwrap()), 3);
+
+        // Wrapping round into an obstacle on the other side turns the guard
+        let field = parse_input(".^.\n...\n.#.");
+        let walk = field.walk(&field.grid, Edges::Wrap);
+        assert_eq!(
+            walk.states[1],
+            Guard {
+                x: 1,
+                y: 0,
+                direction: Direction::East
+            }
+        );
+        assert_eq!(walk.cycle_start, Some(1));
+        assert_eq!(walk.cycle().unwrap().len(), 3);
     }
 }
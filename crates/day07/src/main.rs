@@ -1,88 +1,601 @@
-use itertools::Itertools;
 use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
 
 fn main() {
     let input = include_str!("input.txt");
 
+    if std::env::args().nth(1).as_deref() == Some("sets") {
+        let equations: Vec<Equation<usize>> = input.lines().map(Equation::from_str).collect();
+        let pool = OperatorSet::new(&[
+            (Operator::Add, 1),
+            (Operator::Subtract, 1),
+            (Operator::Multiply, 2),
+            (Operator::Divide, 2),
+            (Operator::Power, 3),
+            (CONCATENATE, 3),
+            (Operator::Concatenate { base: 2 }, 4),
+        ]);
+
+        for operators in pool.subsets() {
+            let solved: Vec<(usize, usize)> = equations
+                .par_iter()
+                .filter_map(|equation| {
+                    equation
+                        .solve_cheapest(&operators)
+                        .map(|(cost, _)| (equation.expected, cost))
+                })
+                .collect();
+
+            // Solved equations can add up past `usize::MAX`
+            let total = solved
+                .iter()
+                .try_fold(0u128, |total, &(expected, _)| {
+                    total.checked_add(expected as u128)
+                })
+                .map_or("overflow".to_string(), |total| total.to_string());
+            println!(
+                "{:<20} solves {:>4} equations, total {:>20}, total cost {:>8}",
+                operators.to_string(),
+                solved.len(),
+                total,
+                solved.iter().map(|(_, cost)| cost).sum::<usize>()
+            );
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("explain") {
+        let operators = OperatorSet::new(&[
+            (Operator::Add, 1),
+            (Operator::Multiply, 1),
+            (CONCATENATE, 1),
+        ]);
+
+        // Only the first solution for each line, unless asked for all of them
+        let all = std::env::args().nth(2).as_deref() == Some("all");
+        for line in input.lines() {
+            let equation: Equation<usize> = Equation::from_str(line);
+            println!(
+                "{}: {} solution(s)",
+                line,
+                equation.count_solutions(&operators)
+            );
+
+            let solutions = if all {
+                equation.solutions(&operators)
+            } else {
+                equation.solve(&operators).into_iter().collect()
+            };
+            for solution in solutions {
+                println!("  {}", Solution::new(&equation, &solution));
+            }
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("u128") {
+        println!(
+            "Part 1: {}",
+            calibrate::<u128>(input, &OperatorSet::part1())
+        );
+        println!(
+            "Part 2: {}",
+            calibrate::<u128>(input, &OperatorSet::part2())
+        );
+        return;
+    }
+
     println!("Part 1: {}", part1(input));
     println!("Part 2: {}", part2(input));
 }
 
+// The unsigned integer operations the solver needs, so it can run on wider
+// types when `usize` isn't enough
+trait Number: Copy + Ord + Hash + fmt::Display + fmt::Debug + FromStr + Send + Sync {
+    const ZERO: Self;
+    const ONE: Self;
+
+    fn from_usize(value: usize) -> Option<Self>;
+    fn to_u32(self) -> Option<u32>;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_div(self, other: Self) -> Option<Self>;
+    fn checked_rem(self, other: Self) -> Option<Self>;
+    fn checked_pow(self, exponent: u32) -> Option<Self>;
+    fn checked_ilog(self, base: Self) -> Option<u32>;
+}
+
+macro_rules! impl_number {
+    ($($t:ty),*) => {
+        $(
+            impl Number for $t {
+                const ZERO: Self = 0;
+                const ONE: Self = 1;
+
+                fn from_usize(value: usize) -> Option<Self> {
+                    Self::try_from(value).ok()
+                }
+
+                fn to_u32(self) -> Option<u32> {
+                    u32::try_from(self).ok()
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$t>::checked_sub(self, other)
+                }
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$t>::checked_mul(self, other)
+                }
+
+                fn checked_div(self, other: Self) -> Option<Self> {
+                    <$t>::checked_div(self, other)
+                }
+
+                fn checked_rem(self, other: Self) -> Option<Self> {
+                    <$t>::checked_rem(self, other)
+                }
+
+                fn checked_pow(self, exponent: u32) -> Option<Self> {
+                    <$t>::checked_pow(self, exponent)
+                }
+
+                fn checked_ilog(self, base: Self) -> Option<u32> {
+                    <$t>::checked_ilog(self, base)
+                }
+            }
+        )*
+    };
+}
+
+impl_number!(usize, u64, u128);
+
 #[derive(Debug)]
-struct Equation {
-    expected: usize,
-    components: Vec<usize>,
+struct Equation<T> {
+    expected: T,
+    components: Vec<T>,
 }
 
-#[derive(Copy, Debug, Clone)]
+#[derive(Copy, Debug, Clone, PartialEq, Eq)]
 enum Operator {
     Add,
+    Subtract,
     Multiply,
-    Concatenate,
+    Divide,
+    Power,
+    Concatenate { base: usize },
+}
+
+const CONCATENATE: Operator = Operator::Concatenate { base: 10 };
+
+// The left operand that must have produced a given result
+enum Undo<T> {
+    Exactly(T),
+    Any,
 }
 
-impl Equation {
+impl Operator {
+    // Results that don't fit, or aren't natural numbers, are `None`. That
+    // includes division with a remainder.
+    fn apply<T: Number>(self, left: T, right: T) -> Option<T> {
+        match self {
+            Operator::Add => left.checked_add(right),
+            Operator::Subtract => left.checked_sub(right),
+            Operator::Multiply => left.checked_mul(right),
+            Operator::Divide => (left.checked_rem(right)? == T::ZERO)
+                .then(|| left.checked_div(right))
+                .flatten(),
+            Operator::Power => match right.to_u32() {
+                Some(exponent) => left.checked_pow(exponent),
+                None if left <= T::ONE => Some(left),
+                None => None,
+            },
+            Operator::Concatenate { base } => match shift(right, base) {
+                Some(shift) => left.checked_mul(shift)?.checked_add(right),
+                None if left == T::ZERO => Some(right),
+                None => None,
+            },
+        }
+    }
+
+    // At least as large as anything `apply` can return for a left operand of
+    // at most `left`, where `None` means there's no telling
+    fn bound<T: Number>(self, left: Option<T>, right: T) -> Option<T> {
+        let left = left?;
+        match self {
+            Operator::Subtract => Some(left.checked_sub(right).unwrap_or(T::ZERO)),
+            Operator::Divide => Some(left.checked_div(right).unwrap_or(T::ZERO)),
+            _ => self.apply(left, right),
+        }
+    }
+
+    fn undo<T: Number>(self, target: T, right: T) -> Option<Undo<T>> {
+        match self {
+            Operator::Add => target.checked_sub(right).map(Undo::Exactly),
+            Operator::Subtract => target.checked_add(right).map(Undo::Exactly),
+            Operator::Multiply if right == T::ZERO => (target == T::ZERO).then_some(Undo::Any),
+            Operator::Multiply => (target.checked_rem(right)? == T::ZERO)
+                .then(|| target.checked_div(right).map(Undo::Exactly))
+                .flatten(),
+            // Dividing by zero is undefined, whatever the target
+            Operator::Divide if right == T::ZERO => None,
+            Operator::Divide => target.checked_mul(right).map(Undo::Exactly),
+            Operator::Power if right == T::ZERO => (target == T::ONE).then_some(Undo::Any),
+            Operator::Power => match right.to_u32() {
+                Some(exponent) => integer_root(target, exponent).map(Undo::Exactly),
+                None => (target <= T::ONE).then_some(Undo::Exactly(target)),
+            },
+            Operator::Concatenate { base } => match shift(right, base) {
+                Some(shift) => (target.checked_rem(shift)? == right)
+                    .then(|| target.checked_div(shift).map(Undo::Exactly))
+                    .flatten(),
+                None => (target == right).then_some(Undo::Exactly(T::ZERO)),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operator::Add => write!(f, "+"),
+            Operator::Subtract => write!(f, "-"),
+            Operator::Multiply => write!(f, "*"),
+            Operator::Divide => write!(f, "/"),
+            Operator::Power => write!(f, "^"),
+            Operator::Concatenate { base: 10 } => write!(f, "||"),
+            Operator::Concatenate { base } => write!(f, "||{}", base),
+        }
+    }
+}
+
+// What the left operand is multiplied by when `right` is concatenated to it
+fn shift<T: Number>(right: T, base: usize) -> Option<T> {
+    let base = T::from_usize(base)?;
+    let right_digits = right.checked_ilog(base).unwrap_or(0) + 1;
+    base.checked_pow(right_digits)
+}
+
+fn integer_root<T: Number>(value: T, degree: u32) -> Option<T> {
+    // Binary search for the largest root whose power doesn't pass `value`
+    let two = T::ONE.checked_add(T::ONE)?;
+    let (mut low, mut high) = (T::ZERO, value);
+    while low < high {
+        // Rounding up, without `high - low + 1` overflowing for the full range
+        let diff = high.checked_sub(low)?;
+        let half = diff.checked_div(two)?.checked_add(diff.checked_rem(two)?)?;
+        let middle = low.checked_add(half)?;
+        match middle.checked_pow(degree) {
+            Some(power) if power <= value => low = middle,
+            _ => high = middle.checked_sub(T::ONE)?,
+        }
+    }
+
+    (low.checked_pow(degree) == Some(value)).then_some(low)
+}
+
+#[derive(Debug, Clone)]
+struct OperatorSet {
+    operators: Vec<(Operator, usize)>,
+}
+
+impl OperatorSet {
+    fn new(operators: &[(Operator, usize)]) -> Self {
+        assert!(
+            operators.iter().all(|(operator, _)| match operator {
+                Operator::Concatenate { base } => *base >= 2,
+                _ => true,
+            }),
+            "Concatenation needs a base of at least 2"
+        );
+
+        Self {
+            operators: operators.to_vec(),
+        }
+    }
+
+    fn cost(&self, operators: &[Operator]) -> usize {
+        operators
+            .iter()
+            .map(|operator| {
+                self.operators
+                    .iter()
+                    .find(|(candidate, _)| candidate == operator)
+                    .map_or(0, |(_, cost)| *cost)
+            })
+            .sum()
+    }
+
+    fn part1() -> Self {
+        Self::new(&[(Operator::Add, 1), (Operator::Multiply, 1)])
+    }
+
+    fn part2() -> Self {
+        Self::new(&[
+            (Operator::Add, 1),
+            (Operator::Multiply, 1),
+            (CONCATENATE, 1),
+        ])
+    }
+
+    // Every non-empty selection of these operators
+    fn subsets(&self) -> impl Iterator<Item = OperatorSet> + '_ {
+        (1..1usize << self.operators.len()).map(|mask| OperatorSet {
+            operators: self
+                .operators
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, operator)| *operator)
+                .collect(),
+        })
+    }
+}
+
+impl fmt::Display for OperatorSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (operator, _)) in self.operators.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(f, "{}{}", separator, operator)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Number> Equation<T> {
     fn from_str(input: &str) -> Self {
+        let parse = |n: &str| {
+            n.parse()
+                .unwrap_or_else(|_| panic!("Invalid or too large number: {}", n))
+        };
+
         let (expected, components) = input.split_once(": ").unwrap();
         Self {
-            expected: expected.parse().unwrap(),
-            components: components
-                .split_ascii_whitespace()
-                .map(|n| n.parse().unwrap())
-                .collect(),
+            expected: parse(expected),
+            components: components.split_ascii_whitespace().map(parse).collect(),
         }
     }
 
-    fn solve(&self, allow_concatenation: bool) -> Option<Vec<Operator>> {
-        'outer: for operators in (0..self.components.len())
-            .map(|_| {
-                if allow_concatenation {
-                    vec![Operator::Add, Operator::Multiply, Operator::Concatenate]
-                } else {
-                    vec![Operator::Add, Operator::Multiply]
-                }
-            })
-            .multi_cartesian_product()
-        {
-            let mut result = self.components[0];
-            for (i, component) in self.components.iter().skip(1).enumerate() {
-                match operators[i] {
-                    Operator::Add => result += component,
-                    Operator::Multiply => result *= component,
-                    Operator::Concatenate => {
-                        let component_digits = component.checked_ilog10().unwrap_or(0) + 1;
-                        result = result * 10usize.pow(component_digits) + component;
-                    }
-                }
+    fn solve(&self, operators: &OperatorSet) -> Option<Vec<Operator>> {
+        let mut solution = None;
+        self.search(operators, &mut |used| {
+            solution = Some(used.to_vec());
+            true
+        });
 
-                if result > self.expected {
-                    continue 'outer;
-                }
+        solution
+    }
+
+    // Tries every solution, so only use this when there aren't too many
+    fn solve_cheapest(&self, operators: &OperatorSet) -> Option<(usize, Vec<Operator>)> {
+        let mut cheapest: Option<(usize, Vec<Operator>)> = None;
+        self.search(operators, &mut |used| {
+            let cost = operators.cost(used);
+            if cheapest.as_ref().is_none_or(|(lowest, _)| cost < *lowest) {
+                cheapest = Some((cost, used.to_vec()));
             }
+            false
+        });
+
+        cheapest
+    }
+
+    fn solutions(&self, operators: &OperatorSet) -> Vec<Vec<Operator>> {
+        let mut solutions = vec![];
+        self.search(operators, &mut |used| {
+            solutions.push(used.to_vec());
+            false
+        });
+
+        solutions
+    }
+
+    fn count_solutions(&self, operators: &OperatorSet) -> usize {
+        let mut count = 0;
+        self.search(operators, &mut |_| {
+            count += 1;
+            false
+        });
 
-            if result == self.expected {
-                return Some(operators);
+        count
+    }
+
+    // Calls `visit` with every assignment of operators that makes the
+    // equation hold, until it returns true.
+    fn search(&self, operators: &OperatorSet, visit: &mut dyn FnMut(&[Operator]) -> bool) -> bool {
+        let Some(&(first, _)) = operators.operators.first() else {
+            return false;
+        };
+
+        // Every operator is non-decreasing in its left operand, so feeding the
+        // largest value forward gives an upper bound for each prefix
+        let mut maxima = vec![Some(self.components[0])];
+        for &component in &self.components[1..] {
+            let previous = *maxima.last().unwrap();
+            maxima.push(
+                operators
+                    .operators
+                    .iter()
+                    .map(|(operator, _)| operator.bound(previous, component))
+                    .try_fold(T::ZERO, |highest, bound| Some(highest.max(bound?))),
+            );
+        }
+
+        let mut search = Search {
+            equation: self,
+            operators,
+            maxima,
+            used: vec![first; self.components.len() - 1],
+            visit,
+            solutions: 0,
+            dead_ends: HashSet::new(),
+        };
+        search.backwards(self.expected, self.components.len() - 1)
+    }
+}
+
+struct Search<'a, T> {
+    equation: &'a Equation<T>,
+    operators: &'a OperatorSet,
+    maxima: Vec<Option<T>>,
+    used: Vec<Operator>,
+    visit: &'a mut dyn FnMut(&[Operator]) -> bool,
+    solutions: usize,
+    // (target, index) pairs known not to lead to any solution
+    dead_ends: HashSet<(T, usize)>,
+}
+
+impl<T: Number> Search<'_, T> {
+    // Work from the last component to the first, undoing each operator, so
+    // that most branches die straight away (a remainder when dividing, a
+    // suffix that doesn't match) instead of being fully evaluated.
+    fn backwards(&mut self, target: T, index: usize) -> bool {
+        let too_large = self.maxima[index].is_some_and(|highest| target > highest);
+        if too_large || self.dead_ends.contains(&(target, index)) {
+            return false;
+        }
+        if index == 0 {
+            return self.equation.components[0] == target && self.visit();
+        }
+
+        let solutions = self.solutions;
+        let component = self.equation.components[index];
+        for &(operator, _) in &self.operators.operators {
+            self.used[index - 1] = operator;
+            let done = match operator.undo(target, component) {
+                Some(Undo::Exactly(previous)) => self.backwards(previous, index - 1),
+                // Anything before this works, as long as it can be evaluated
+                Some(Undo::Any) => self.forwards(self.equation.components[0], 1, index),
+                None => false,
+            };
+
+            if done {
+                return true;
+            }
+        }
+
+        if self.solutions == solutions {
+            self.dead_ends.insert((target, index));
+        }
+
+        false
+    }
+
+    fn visit(&mut self) -> bool {
+        self.solutions += 1;
+        (self.visit)(&self.used)
+    }
+
+    fn forwards(&mut self, value: T, index: usize, end: usize) -> bool {
+        if index == end {
+            return self.visit();
+        }
+
+        for &(operator, _) in &self.operators.operators {
+            let Some(next) = operator.apply(value, self.equation.components[index]) else {
+                continue;
+            };
+
+            self.used[index - 1] = operator;
+            if self.forwards(next, index + 1, end) {
+                return true;
             }
         }
 
-        None
+        false
     }
 }
 
-fn part1(input: &str) -> usize {
-    let equations: Vec<Equation> = input.lines().map(Equation::from_str).collect();
+// An equation with its operators filled in, which displays as
+// `292 = 11 + 6 * 16 + 20 (11 + 6 = 17, 17 * 16 = 272, 272 + 20 = 292)`
+struct Solution<'a, T> {
+    equation: &'a Equation<T>,
+    operators: &'a [Operator],
+}
+
+impl<'a, T: Number> Solution<'a, T> {
+    fn new(equation: &'a Equation<T>, operators: &'a [Operator]) -> Self {
+        assert_eq!(operators.len() + 1, equation.components.len());
+        Self {
+            equation,
+            operators,
+        }
+    }
+}
+
+impl<T: Number> fmt::Display for Solution<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let components = &self.equation.components;
+        write!(f, "{} = {}", self.equation.expected, components[0])?;
+        for (operator, component) in self.operators.iter().zip(&components[1..]) {
+            write!(f, " {} {}", operator, component)?;
+        }
+
+        if self.operators.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, " (")?;
+        let mut result = Some(components[0]);
+        for (i, (operator, component)) in self.operators.iter().zip(&components[1..]).enumerate() {
+            let Some(left) = result else {
+                break;
+            };
+
+            result = operator.apply(left, *component);
+            let separator = if i == 0 { "" } else { ", " };
+            match result {
+                Some(value) => write!(
+                    f,
+                    "{}{} {} {} = {}",
+                    separator, left, operator, component, value
+                )?,
+                None => write!(
+                    f,
+                    "{}{} {} {} is undefined",
+                    separator, left, operator, component
+                )?,
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+// Sums the equations that can be solved, and panics if even that sum doesn't
+// fit in `T`
+fn calibrate<T: Number>(input: &str, operators: &OperatorSet) -> T {
+    let equations: Vec<Equation<T>> = input.lines().map(Equation::from_str).collect();
     equations
         .par_iter()
-        .filter_map(|equation| equation.solve(false).map(|_| equation.expected))
-        .sum()
+        .filter_map(|equation| equation.solve(operators).map(|_| equation.expected))
+        .map(Some)
+        .reduce(
+            || Some(T::ZERO),
+            |left, right| {
+                left.zip(right)
+                    .and_then(|(left, right)| left.checked_add(right))
+            },
+        )
+        .expect("Calibration result doesn't fit")
+}
+
+fn part1(input: &str) -> usize {
+    calibrate(input, &OperatorSet::part1())
 }
 
 fn part2(input: &str) -> usize {
-    let equations: Vec<Equation> = input.lines().map(Equation::from_str).collect();
-    equations
-        .par_iter()
-        .filter_map(|equation| equation.solve(true).map(|_| equation.expected))
-        .sum()
+    calibrate(input, &OperatorSet::part2())
 }
 
 #[cfg(test)]
@@ -108,4 +621,310 @@ mod tests {
     fn test_day7_part2() {
         assert_eq!(part2(TEST_INPUT), 11387);
     }
+
+    fn evaluate(components: &[usize], operators: &[Operator]) -> usize {
+        operators
+            .iter()
+            .zip(&components[1..])
+            .fold(components[0], |result, (operator, component)| {
+                operator.apply(result, *component).unwrap()
+            })
+    }
+
+    #[test]
+    fn test_day7_witness() {
+        for line in TEST_INPUT.lines() {
+            let equation = Equation::<usize>::from_str(line);
+            if let Some(operators) = equation.solve(&OperatorSet::new(&[
+                (Operator::Add, 1),
+                (Operator::Multiply, 1),
+                (CONCATENATE, 1),
+            ])) {
+                assert_eq!(operators.len(), equation.components.len() - 1);
+                assert_eq!(
+                    evaluate(&equation.components, &operators),
+                    equation.expected
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_day7_many_components() {
+        // 2^39 operator combinations, far too many to enumerate
+        let components: Vec<usize> = (0..40).map(|i| i % 3 + 1).collect();
+        let operators: Vec<Operator> = (0..39)
+            .map(|i| match i % 4 {
+                0 => Operator::Multiply,
+                _ => Operator::Add,
+            })
+            .collect();
+        let equation = Equation {
+            expected: evaluate(&components, &operators),
+            components,
+        };
+        let solution = equation
+            .solve(&OperatorSet::new(&[
+                (Operator::Add, 1),
+                (Operator::Multiply, 1),
+            ]))
+            .unwrap();
+        assert_eq!(evaluate(&equation.components, &solution), equation.expected);
+
+        let equation = Equation {
+            expected: 1_000_001usize,
+            components: vec![2; 40],
+        };
+        assert_eq!(
+            equation.solve(&OperatorSet::new(&[
+                (Operator::Add, 1),
+                (Operator::Multiply, 1),
+                (CONCATENATE, 1)
+            ])),
+            None
+        );
+    }
+
+    #[test]
+    fn test_day7_operator_sets() {
+        let pool = OperatorSet::new(&[
+            (Operator::Subtract, 1),
+            (Operator::Divide, 1),
+            (Operator::Power, 1),
+            (Operator::Concatenate { base: 2 }, 1),
+        ]);
+        let solve = |line: &str| Equation::<usize>::from_str(line).solve(&pool);
+
+        assert_eq!(solve("2: 7 5"), Some(vec![Operator::Subtract]));
+        assert_eq!(solve("3: 15 5"), Some(vec![Operator::Divide]));
+        assert_eq!(solve("3: 17 5"), None);
+        assert_eq!(
+            solve("2: 2 3 4"),
+            Some(vec![Operator::Power, Operator::Divide])
+        );
+        assert_eq!(
+            solve("7: 3 1"),
+            Some(vec![Operator::Concatenate { base: 2 }])
+        );
+        assert_eq!(
+            solve("17: 2 3 1"),
+            Some(vec![Operator::Power, Operator::Concatenate { base: 2 }])
+        );
+
+        let equation = Equation::<usize>::from_str("156: 15 6");
+        let pool = OperatorSet::new(&[
+            (Operator::Add, 1),
+            (Operator::Multiply, 1),
+            (CONCATENATE, 1),
+        ]);
+        let solvable: Vec<String> = pool
+            .subsets()
+            .filter(|operators| equation.solve(operators).is_some())
+            .map(|operators| operators.to_string())
+            .collect();
+        assert_eq!(solvable, ["||", "+ ||", "* ||", "+ * ||"]);
+    }
+
+    #[test]
+    fn test_day7_cheapest() {
+        let equation = Equation::<usize>::from_str("4: 2 2");
+        let operators = OperatorSet::new(&[(Operator::Multiply, 1), (Operator::Add, 3)]);
+        assert_eq!(
+            equation.solve_cheapest(&operators),
+            Some((1, vec![Operator::Multiply]))
+        );
+
+        let operators = OperatorSet::new(&[(Operator::Multiply, 3), (Operator::Add, 1)]);
+        assert_eq!(
+            equation.solve_cheapest(&operators),
+            Some((1, vec![Operator::Add]))
+        );
+
+        // Anything multiplied by zero works, but subtracting can't go negative
+        let equation = Equation::<usize>::from_str("0: 1 2 0");
+        let operators = OperatorSet::new(&[(Operator::Subtract, 1), (Operator::Multiply, 5)]);
+        assert_eq!(
+            equation.solve_cheapest(&operators),
+            Some((10, vec![Operator::Multiply, Operator::Multiply]))
+        );
+    }
+
+    #[test]
+    fn test_day7_explain() {
+        let operators = OperatorSet::new(&[
+            (Operator::Add, 1),
+            (Operator::Multiply, 1),
+            (CONCATENATE, 1),
+        ]);
+
+        let equation = Equation::<usize>::from_str("292: 11 6 16 20");
+        let solutions = equation.solutions(&operators);
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(
+            Solution::new(&equation, &solutions[0]).to_string(),
+            "292 = 11 + 6 * 16 + 20 (11 + 6 = 17, 17 * 16 = 272, 272 + 20 = 292)"
+        );
+
+        let equation = Equation::<usize>::from_str("7290: 6 8 6 15");
+        assert_eq!(
+            Solution::new(&equation, &equation.solve(&operators).unwrap()).to_string(),
+            "7290 = 6 * 8 || 6 * 15 (6 * 8 = 48, 48 || 6 = 486, 486 * 15 = 7290)"
+        );
+
+        let counts: Vec<usize> = TEST_INPUT
+            .lines()
+            .map(|line| Equation::<usize>::from_str(line).count_solutions(&operators))
+            .collect();
+        assert_eq!(counts, [1, 2, 0, 1, 1, 0, 1, 0, 1]);
+
+        // Multiplying by one doesn't change anything, so every mix works
+        let equation = Equation::<usize>::from_str("1: 1 1 1 1 1 1");
+        assert_eq!(
+            equation.count_solutions(&OperatorSet::new(&[
+                (Operator::Multiply, 1),
+                (Operator::Power, 1),
+                (Operator::Divide, 1),
+            ])),
+            3usize.pow(5)
+        );
+    }
+
+    // Tries every combination of operators, evaluating left to right
+    fn count_by_brute_force(equation: &Equation<usize>, operators: &OperatorSet) -> usize {
+        let choices = operators.operators.len();
+        let gaps = equation.components.len() - 1;
+        (0..choices.pow(gaps as u32))
+            .filter(|combination| {
+                (0..gaps).try_fold(equation.components[0], |result, i| {
+                    let (operator, _) =
+                        operators.operators[combination / choices.pow(i as u32) % choices];
+                    operator.apply(result, equation.components[i + 1])
+                }) == Some(equation.expected)
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_day7_matches_brute_force() {
+        let operators = OperatorSet::new(&[
+            (Operator::Add, 1),
+            (Operator::Subtract, 1),
+            (Operator::Multiply, 1),
+            (Operator::Divide, 1),
+            (Operator::Power, 1),
+            (CONCATENATE, 1),
+            (Operator::Concatenate { base: 2 }, 1),
+        ]);
+
+        // Nothing divided by zero is zero, or anything else
+        let equation = Equation::<usize>::from_str("31: 3 3 0");
+        assert_eq!(equation.count_solutions(&operators), 0);
+        let equation = Equation::<usize>::from_str("0: 3 3 0");
+        assert_eq!(
+            equation.count_solutions(&operators),
+            count_by_brute_force(&equation, &operators)
+        );
+
+        let mut seed = 7usize;
+        let mut random = |limit: usize| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % limit
+        };
+        for _ in 0..2000 {
+            let components: Vec<usize> = (0..random(3) + 2).map(|_| random(5)).collect();
+            let equation = Equation {
+                expected: random(40),
+                components,
+            };
+            assert_eq!(
+                equation.count_solutions(&operators),
+                count_by_brute_force(&equation, &operators),
+                "{:?}",
+                equation
+            );
+        }
+    }
+
+    #[test]
+    fn test_day7_overflow() {
+        let operators = OperatorSet::part2();
+
+        let equation = Equation {
+            expected: usize::MAX,
+            components: vec![usize::MAX / 3, 3],
+        };
+        assert_eq!(equation.solve(&operators), Some(vec![Operator::Multiply]));
+
+        // Going past `usize::MAX` on the way counts as going past the target
+        let equation = Equation {
+            expected: 5,
+            components: vec![usize::MAX, 2, 5],
+        };
+        assert_eq!(equation.count_solutions(&operators), 0);
+        assert_eq!(
+            Solution::new(&equation, &[Operator::Add, Operator::Add]).to_string(),
+            format!(
+                "5 = {} + 2 + 5 ({} + 2 is undefined)",
+                usize::MAX,
+                usize::MAX
+            )
+        );
+
+        // Numbers whose shift would overflow can only follow a zero
+        let equation = Equation {
+            expected: usize::MAX,
+            components: vec![0, usize::MAX],
+        };
+        assert_eq!(equation.count_solutions(&operators), 2);
+        let equation = Equation {
+            expected: usize::MAX,
+            components: vec![1, usize::MAX],
+        };
+        assert_eq!(equation.solve(&operators), Some(vec![Operator::Multiply]));
+
+        let pool = OperatorSet::new(&[
+            (Operator::Subtract, 1),
+            (Operator::Power, 1),
+            (Operator::Concatenate { base: 2 }, 1),
+        ]);
+        let equation = Equation {
+            expected: usize::MAX - 1,
+            components: vec![usize::MAX / 2, 2, usize::MAX],
+        };
+        assert_eq!(equation.count_solutions(&pool), 0);
+        let equation = Equation {
+            expected: 1 << (usize::BITS - 1),
+            components: vec![2, (usize::BITS - 1) as usize],
+        };
+        assert_eq!(equation.solve(&pool), Some(vec![Operator::Power]));
+
+        // Searching for a root of the largest value there is
+        let pool = OperatorSet::new(&[(Operator::Power, 1)]);
+        let equation = Equation {
+            expected: usize::MAX,
+            components: vec![usize::MAX, 1],
+        };
+        assert_eq!(equation.count_solutions(&pool), 1);
+        let equation = Equation {
+            expected: u128::MAX,
+            components: vec![u128::MAX, 1],
+        };
+        assert_eq!(equation.count_solutions(&pool), 1);
+    }
+
+    #[test]
+    fn test_day7_u128() {
+        let input = "20282409603651670423947251286016: 18446744073709551616 1099511627776
+12345678901234567890123: 1234567890 1234567890123
+190: 10 19";
+
+        assert_eq!(
+            calibrate::<u128>(input, &OperatorSet::part1()),
+            20282409603651670423947251286016 + 190
+        );
+        assert_eq!(
+            calibrate::<u128>(input, &OperatorSet::part2()),
+            20282409603651670423947251286016 + 12345678901234567890123 + 190
+        );
+    }
 }
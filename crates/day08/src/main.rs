@@ -4,6 +4,21 @@ fn main() {
     let input = include_str!("input.txt");
     let input = parse_input(input);
 
+    if std::env::args().nth(1).as_deref() == Some("antinodes") {
+        // Either "collinear" or a list of ratios such as "-1 2 1/3 2/3"
+        let args: Vec<_> = std::env::args().skip(2).collect();
+        let antinodes = if args.iter().any(|arg| arg == "collinear") {
+            Antinodes::Collinear
+        } else if args.is_empty() {
+            Antinodes::part1()
+        } else {
+            Antinodes::Ratios(args.iter().map(|arg| Ratio::parse(arg)).collect())
+        };
+
+        println!("Antinodes: {}", input.antinodes(&antinodes).len());
+        return;
+    }
+
     println!("Part 1: {}", part1(&input));
     println!("Part 2: {}", part2(&input));
 }
@@ -27,116 +42,143 @@ struct Point {
     y: usize,
 }
 
-fn part1(input: &Input) -> usize {
-    let Input {
-        grid_width,
-        grid_height,
-        antenna_map,
-    } = input;
-
-    let antinode_points: HashSet<_> = antenna_map
-        .values()
-        .filter(|points| points.len() > 1)
-        .flat_map(|points| {
-            points.iter().enumerate().flat_map(|(i, point_a)| {
-                points.iter().skip(i + 1).flat_map(move |point_b| {
-                    let mut antinode_points = Vec::new();
-                    let x_diff = point_b.x as isize - point_a.x as isize;
-                    let y_diff = point_b.y as isize - point_a.y as isize;
-
-                    let antinode1_x = point_a.x as isize - x_diff;
-                    let antinode1_y = point_a.y as isize - y_diff;
-                    if antinode1_x >= 0
-                        && antinode1_y >= 0
-                        && antinode1_x < *grid_width as isize
-                        && antinode1_y < *grid_height as isize
-                    {
-                        antinode_points.push(Point {
-                            x: antinode1_x as usize,
-                            y: antinode1_y as usize,
-                        });
-                    }
+// Where along the line through a pair of antennas an antinode appears,
+// measured from the first antenna in units of the distance to the second
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Ratio {
+    numerator: isize,
+    denominator: isize,
+}
 
-                    let antinode2_x = point_b.x as isize + x_diff;
-                    let antinode2_y = point_b.y as isize + y_diff;
-                    if antinode2_x >= 0
-                        && antinode2_y >= 0
-                        && antinode2_x < *grid_width as isize
-                        && antinode2_y < *grid_height as isize
-                    {
-                        antinode_points.push(Point {
-                            x: antinode2_x as usize,
-                            y: antinode2_y as usize,
-                        });
-                    }
+impl Ratio {
+    fn parse(text: &str) -> Ratio {
+        let (numerator, denominator) = text.split_once('/').unwrap_or((text, "1"));
+        let ratio = Ratio {
+            numerator: numerator.parse().unwrap(),
+            denominator: denominator.parse().unwrap(),
+        };
+        assert_ne!(ratio.denominator, 0, "Invalid ratio: {}", text);
+        ratio
+    }
 
-                    antinode_points.into_iter()
-                })
+    // The offset from the first antenna, if it lands exactly on the grid
+    fn apply(&self, diff: isize) -> Option<isize> {
+        let scaled = diff * self.numerator;
+        if scaled % self.denominator == 0 {
+            Some(scaled / self.denominator)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Antinodes {
+    // At fixed ratios of the distance between each pair
+    Ratios(Vec<Ratio>),
+    // At every grid point in line with a pair
+    Collinear,
+}
+
+impl Antinodes {
+    fn part1() -> Antinodes {
+        // Twice as far from one antenna as from the other, on the outside
+        Antinodes::Ratios(vec![
+            Ratio {
+                numerator: -1,
+                denominator: 1,
+            },
+            Ratio {
+                numerator: 2,
+                denominator: 1,
+            },
+        ])
+    }
+}
+
+fn gcd(a: isize, b: isize) -> isize {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Input {
+    fn point_at(&self, x: isize, y: isize) -> Option<Point> {
+        if x >= 0 && y >= 0 && x < self.grid_width as isize && y < self.grid_height as isize {
+            Some(Point {
+                x: x as usize,
+                y: y as usize,
+            })
+        } else {
+            None
+        }
+    }
+
+    // Every pair of antennas sharing a frequency
+    fn pairs(&self) -> impl Iterator<Item = (&Point, &Point)> {
+        self.antenna_map.values().flat_map(|points| {
+            points.iter().enumerate().flat_map(move |(i, point_a)| {
+                points[i + 1..]
+                    .iter()
+                    .map(move |point_b| (point_a, point_b))
             })
         })
-        .collect();
+    }
 
-    antinode_points.len()
-}
+    fn pair_antinodes(
+        &self,
+        point_a: &Point,
+        point_b: &Point,
+        antinodes: &Antinodes,
+    ) -> Vec<Point> {
+        let (a_x, a_y) = (point_a.x as isize, point_a.y as isize);
+        let x_diff = point_b.x as isize - a_x;
+        let y_diff = point_b.y as isize - a_y;
 
-fn part2(input: &Input) -> usize {
-    let Input {
-        grid_width,
-        grid_height,
-        antenna_map,
-    } = input;
-
-    let antinode_points: HashSet<_> = antenna_map
-        .values()
-        .filter(|points| points.len() > 1)
-        .flat_map(|points| {
-            points.iter().enumerate().flat_map(|(i, point_a)| {
-                points.iter().skip(i + 1).flat_map(move |point_b| {
-                    let mut antinode_points = vec![point_a.clone(), point_b.clone()];
-                    let x_diff = point_b.x as isize - point_a.x as isize;
-                    let y_diff = point_b.y as isize - point_a.y as isize;
-
-                    for i in 1.. {
-                        let antinode1_x = point_a.x as isize - x_diff * i;
-                        let antinode1_y = point_a.y as isize - y_diff * i;
-                        if antinode1_x >= 0
-                            && antinode1_y >= 0
-                            && antinode1_x < *grid_width as isize
-                            && antinode1_y < *grid_height as isize
-                        {
-                            antinode_points.push(Point {
-                                x: antinode1_x as usize,
-                                y: antinode1_y as usize,
-                            });
-                        } else {
-                            break;
-                        }
-                    }
+        match antinodes {
+            Antinodes::Ratios(ratios) => ratios
+                .iter()
+                .filter_map(|ratio| {
+                    self.point_at(a_x + ratio.apply(x_diff)?, a_y + ratio.apply(y_diff)?)
+                })
+                .collect(),
+            Antinodes::Collinear => {
+                // Reduce the step so no grid points in between are skipped
+                let divisor = gcd(x_diff, y_diff);
+                let (x_step, y_step) = (x_diff / divisor, y_diff / divisor);
 
-                    for i in 1.. {
-                        let antinode2_x = point_b.x as isize + x_diff * i;
-                        let antinode2_y = point_b.y as isize + y_diff * i;
-                        if antinode2_x >= 0
-                            && antinode2_y >= 0
-                            && antinode2_x < *grid_width as isize
-                            && antinode2_y < *grid_height as isize
+                let mut points = vec![];
+                for direction in [1, -1] {
+                    let start = if direction == 1 { 0 } else { 1 };
+                    for i in start.. {
+                        match self
+                            .point_at(a_x + x_step * i * direction, a_y + y_step * i * direction)
                         {
-                            antinode_points.push(Point {
-                                x: antinode2_x as usize,
-                                y: antinode2_y as usize,
-                            });
-                        } else {
-                            break;
+                            Some(point) => points.push(point),
+                            None => break,
                         }
                     }
+                }
+                points
+            }
+        }
+    }
 
-                    antinode_points.into_iter()
-                })
-            })
-        })
-        .collect();
+    fn antinodes(&self, antinodes: &Antinodes) -> HashSet<Point> {
+        self.pairs()
+            .flat_map(|(point_a, point_b)| self.pair_antinodes(point_a, point_b, antinodes))
+            .collect()
+    }
+}
 
-    antinode_points.len()
+fn part1(input: &Input) -> usize {
+    input.antinodes(&Antinodes::part1()).len()
+}
+
+fn part2(input: &Input) -> usize {
+    input.antinodes(&Antinodes::Collinear).len()
 }
 
 fn parse_input(input: &str) -> Input {
@@ -202,4 +244,47 @@ mod tests {
         let input = parse_input(TEST_INPUT);
         assert_eq!(part2(&input), 34);
     }
+
+    #[test]
+    fn test_day8_ratios() {
+        let input = parse_input(TEST_INPUT);
+        let ratios =
+            |ratios: &[&str]| Antinodes::Ratios(ratios.iter().map(|r| Ratio::parse(r)).collect());
+        assert_eq!(
+            input.antinodes(&ratios(&["-1", "2"])),
+            input.antinodes(&Antinodes::part1())
+        );
+
+        // The antennas themselves
+        assert_eq!(input.antinodes(&ratios(&["0", "1"])).len(), 7);
+
+        // Thirds only land on the grid when the distance divides by three
+        let input = parse_input("a..a..a\n.......");
+        assert_eq!(
+            input.antinodes(&ratios(&["1/3", "2/3"])),
+            HashSet::from([
+                Point { x: 1, y: 0 },
+                Point { x: 2, y: 0 },
+                Point { x: 4, y: 0 },
+                Point { x: 5, y: 0 }
+            ])
+        );
+        assert_eq!(
+            input.antinodes(&ratios(&["1/2"])),
+            HashSet::from([Point { x: 3, y: 0 }])
+        );
+    }
+
+    #[test]
+    fn test_day8_collinear() {
+        // The step between these is (2, 2), but every diagonal point is in line
+        let input = parse_input("a....\n.....\n..a..\n.....\n.....");
+        assert_eq!(part2(&input), 5);
+
+        let input = parse_input("....\n.a..\n...a\n....");
+        assert_eq!(
+            input.antinodes(&Antinodes::Collinear),
+            HashSet::from([Point { x: 1, y: 1 }, Point { x: 3, y: 2 }])
+        );
+    }
 }
@@ -1,11 +1,59 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
 fn main() {
     let input = include_str!("input.txt");
 
+    if std::env::args().nth(1).as_deref() == Some("trace") {
+        let blocks = parse_blocks(input);
+        let disk = Disk::from_blocks(&blocks);
+        println!("Disk: {}", render_blocks(&blocks));
+
+        for (part, mut compaction) in [(1, compact_blocks(&disk)), (2, compact_files(&disk))] {
+            if part == 1 {
+                compaction.moves = compaction
+                    .moves
+                    .into_iter()
+                    .flat_map(Move::blocks)
+                    .collect();
+            }
+
+            println!("Part {}:", part);
+            for line in trace(&blocks, &compaction.moves) {
+                println!("{}", line);
+            }
+
+            // Only the shape of the disk survives, not which file is where
+            let compacted = blocks_from_segments(&compaction.segments);
+            println!("Dense map (ids by order): {}", to_dense_map(&compacted));
+        }
+
+        return;
+    }
+
+    if std::env::args().nth(1).as_deref() == Some("compare") {
+        let disk = Disk::from_blocks(&parse_blocks(input));
+
+        for strategy in STRATEGIES {
+            let report = Report::new(&strategy.compact(&disk));
+            println!(
+                "{:<14} checksum {:>16}  moves {:>6}  free gaps {:>6}  fragmented files {:>6}",
+                strategy.name(),
+                report.checksum,
+                report.moves,
+                report.free_gaps,
+                report.fragmented_files
+            );
+        }
+
+        return;
+    }
+
     println!("Part 1: {}", part1(input));
     println!("Part 2: {}", part2(input));
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 enum Block {
     File { id: usize, length: usize },
     Empty { length: usize },
@@ -39,90 +87,504 @@ fn parse_blocks(input: &str) -> Vec<Block> {
     blocks
 }
 
-fn blocks_to_flat_list(blocks: Vec<Block>) -> Vec<Option<usize>> {
-    blocks
-        .into_iter()
-        .flat_map(|x| match x {
-            Block::File { id, length } => vec![Some(id); length],
-            Block::Empty { length } => vec![None; length],
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segment {
+    id: usize,
+    position: usize,
+    length: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    position: usize,
+    length: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Move {
+    id: usize,
+    from: usize,
+    to: usize,
+    length: usize,
+}
+
+impl Move {
+    // Split into single-block moves, taking blocks from the end of the source
+    // first like the puzzle's walkthrough does.
+    fn blocks(self) -> impl Iterator<Item = Move> {
+        (0..self.length).map(move |i| Move {
+            id: self.id,
+            from: self.from + self.length - 1 - i,
+            to: self.to + i,
+            length: 1,
         })
-        .collect()
+    }
 }
 
-fn part1(input: &str) -> usize {
-    let mut blocks = blocks_to_flat_list(parse_blocks(input));
+#[derive(Debug)]
+struct Compaction {
+    segments: Vec<Segment>,
+    moves: Vec<Move>,
+}
 
-    let mut search_empty_from = 0;
-    for i in (0..blocks.len()).rev() {
-        if blocks[i].is_some() {
-            // Find the first empty spot
-            let empty_slot = match blocks
-                .iter()
-                .skip(search_empty_from)
-                .position(|x| x.is_none())
-            {
-                Some(x) => x,
-                None => break,
-            } + search_empty_from;
-            search_empty_from = empty_slot;
+#[derive(Debug)]
+struct Disk {
+    files: Vec<Segment>,
+    free: Vec<Span>,
+}
+
+impl Disk {
+    fn from_blocks(blocks: &[Block]) -> Self {
+        let mut files = Vec::new();
+        let mut free = Vec::new();
+        let mut position = 0;
+
+        for block in blocks {
+            match *block {
+                Block::File { id, length } => files.push(Segment {
+                    id,
+                    position,
+                    length,
+                }),
+                Block::Empty { length } => free.push(Span { position, length }),
+            }
+            position += block.length();
+        }
+
+        Self { files, free }
+    }
+}
+
+impl Block {
+    fn length(&self) -> usize {
+        match self {
+            Block::File { length, .. } | Block::Empty { length } => *length,
+        }
+    }
+}
+
+// Spans never grow, and the dense format caps them at 9 blocks, so one bucket
+// per length is enough. Each bucket is a min-heap on position.
+const MAX_SPAN_LENGTH: usize = 9;
+
+struct FreeSpace {
+    buckets: [BinaryHeap<Reverse<(usize, usize)>>; MAX_SPAN_LENGTH + 1],
+}
+
+impl FreeSpace {
+    fn new(spans: &[Span]) -> Self {
+        let mut free_space = Self {
+            buckets: Default::default(),
+        };
+        for &span in spans {
+            free_space.insert(span);
+        }
+
+        free_space
+    }
+
+    fn insert(&mut self, span: Span) {
+        if span.length > 0 {
+            self.buckets[span.length.min(MAX_SPAN_LENGTH)]
+                .push(Reverse((span.position, span.length)));
+        }
+    }
+
+    // Take `length` blocks from a span that fits them and starts before
+    // `before`, putting whatever is left of that span back. There's no point
+    // moving zero blocks, so that never finds anything.
+    fn take(&mut self, length: usize, before: usize, fit: Fit) -> Option<usize> {
+        if length == 0 {
+            return None;
+        }
+
+        let mut candidates = (length..self.buckets.len())
+            .filter_map(|bucket| {
+                self.buckets[bucket]
+                    .peek()
+                    .map(|Reverse((position, _))| (*position, bucket))
+            })
+            .filter(|(position, _)| *position < before);
+        let (_, bucket) = match fit {
+            Fit::Leftmost => candidates.min(),
+            Fit::Best => candidates.next(),
+            Fit::Worst => candidates.next_back(),
+        }?;
+
+        let Reverse((position, span_length)) = self.buckets[bucket].pop().unwrap();
+        self.insert(Span {
+            position: position + length,
+            length: span_length - length,
+        });
+
+        Some(position)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Fit {
+    Leftmost,
+    Best,
+    Worst,
+}
+
+// Move single blocks from the end of the disk into the leftmost free block,
+// which splits files across however many free spans they need.
+fn compact_blocks(disk: &Disk) -> Compaction {
+    let mut segments = Vec::with_capacity(disk.files.len());
+    let mut moves = Vec::new();
+    let mut free = disk.free.iter().copied().filter(|span| span.length > 0);
+    let mut current = free.next();
+
+    for file in disk.files.iter().rev() {
+        let mut remaining = file.length;
+        while remaining > 0 {
+            let Some(span) = current
+                .as_mut()
+                .filter(|span| span.position < file.position)
+            else {
+                break;
+            };
+
+            let moved = remaining.min(span.length);
+            moves.push(Move {
+                id: file.id,
+                from: file.position + remaining - moved,
+                to: span.position,
+                length: moved,
+            });
+            segments.push(Segment {
+                id: file.id,
+                position: span.position,
+                length: moved,
+            });
+            span.position += moved;
+            span.length -= moved;
+            remaining -= moved;
+
+            if span.length == 0 {
+                current = free.next();
+            }
+        }
+
+        if remaining > 0 {
+            segments.push(Segment {
+                length: remaining,
+                ..*file
+            });
+        }
+    }
+
+    Compaction { segments, moves }
+}
+
+// Move whole files, highest id first, into the leftmost span that fits them.
+fn compact_files(disk: &Disk) -> Compaction {
+    move_files(disk, disk.files.iter().rev(), Fit::Leftmost)
+}
+
+// Move each file at most once, in the given order, into a span left of it.
+// The space a file leaves behind can be reused, but isn't merged with its
+// neighbours.
+fn move_files<'a>(disk: &Disk, order: impl Iterator<Item = &'a Segment>, fit: Fit) -> Compaction {
+    let mut free = FreeSpace::new(&disk.free);
+    let mut moves = Vec::new();
+
+    let segments = order
+        .map(|file| match free.take(file.length, file.position, fit) {
+            Some(position) => {
+                moves.push(Move {
+                    id: file.id,
+                    from: file.position,
+                    to: position,
+                    length: file.length,
+                });
+                free.insert(Span {
+                    position: file.position,
+                    length: file.length,
+                });
+                Segment { position, ..*file }
+            }
+            None => *file,
+        })
+        .collect();
+
+    Compaction { segments, moves }
+}
+
+// Slide every file left against its predecessor, leaving all free space in
+// one span at the end of the disk.
+fn defragment(disk: &Disk) -> Compaction {
+    let mut moves = Vec::new();
+    let mut position = 0;
+
+    let segments = disk
+        .files
+        .iter()
+        .map(|file| {
+            if file.position != position && file.length > 0 {
+                moves.push(Move {
+                    id: file.id,
+                    from: file.position,
+                    to: position,
+                    length: file.length,
+                });
+            }
+            let segment = Segment { position, ..*file };
+            position += file.length;
+            segment
+        })
+        .collect();
+
+    Compaction { segments, moves }
+}
+
+trait Strategy {
+    fn name(&self) -> &'static str;
+    fn compact(&self, disk: &Disk) -> Compaction;
+}
+
+struct BlockFill;
+struct FirstFit;
+struct BestFit;
+struct WorstFit;
+struct Defragment;
+struct LargestFirst;
+
+impl Strategy for BlockFill {
+    fn name(&self) -> &'static str {
+        "block fill"
+    }
+
+    fn compact(&self, disk: &Disk) -> Compaction {
+        compact_blocks(disk)
+    }
+}
+
+impl Strategy for FirstFit {
+    fn name(&self) -> &'static str {
+        "first fit"
+    }
+
+    fn compact(&self, disk: &Disk) -> Compaction {
+        compact_files(disk)
+    }
+}
+
+impl Strategy for BestFit {
+    fn name(&self) -> &'static str {
+        "best fit"
+    }
+
+    fn compact(&self, disk: &Disk) -> Compaction {
+        move_files(disk, disk.files.iter().rev(), Fit::Best)
+    }
+}
+
+impl Strategy for WorstFit {
+    fn name(&self) -> &'static str {
+        "worst fit"
+    }
+
+    fn compact(&self, disk: &Disk) -> Compaction {
+        move_files(disk, disk.files.iter().rev(), Fit::Worst)
+    }
+}
+
+impl Strategy for Defragment {
+    fn name(&self) -> &'static str {
+        "defragment"
+    }
+
+    fn compact(&self, disk: &Disk) -> Compaction {
+        defragment(disk)
+    }
+}
+
+impl Strategy for LargestFirst {
+    fn name(&self) -> &'static str {
+        "largest first"
+    }
+
+    fn compact(&self, disk: &Disk) -> Compaction {
+        let mut order: Vec<&Segment> = disk.files.iter().collect();
+        order.sort_by_key(|file| Reverse((file.length, file.id)));
+        move_files(disk, order.into_iter(), Fit::Leftmost)
+    }
+}
+
+const STRATEGIES: [&dyn Strategy; 6] = [
+    &BlockFill,
+    &FirstFit,
+    &BestFit,
+    &WorstFit,
+    &Defragment,
+    &LargestFirst,
+];
+
+#[derive(Debug, PartialEq, Eq)]
+struct Report {
+    checksum: usize,
+    moves: usize,
+    // Runs of free blocks with file blocks on both sides
+    free_gaps: usize,
+    // Files that no longer occupy a single contiguous run
+    fragmented_files: usize,
+}
+
+impl Report {
+    fn new(compaction: &Compaction) -> Self {
+        let mut segments: Vec<Segment> = compaction
+            .segments
+            .iter()
+            .copied()
+            .filter(|segment| segment.length > 0)
+            .collect();
+        segments.sort_by_key(|segment| segment.position);
+
+        let mut free_gaps = 0;
+        let mut pieces = HashMap::new();
+        let mut previous: Option<Segment> = None;
+        for segment in segments {
+            match previous {
+                Some(p) if p.position + p.length < segment.position => free_gaps += 1,
+                // Another piece of the same run, so just extend it
+                Some(p) if p.id == segment.id => {
+                    previous = Some(Segment {
+                        length: segment.position + segment.length - p.position,
+                        ..p
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+            *pieces.entry(segment.id).or_insert(0) += 1;
+            previous = Some(segment);
+        }
 
-            blocks.swap_remove(empty_slot);
+        Self {
+            checksum: checksum(&compaction.segments),
+            moves: compaction.moves.len(),
+            free_gaps,
+            fragmented_files: pieces.values().filter(|&&count| count > 1).count(),
         }
     }
+}
 
-    checksum(blocks)
+fn part1(input: &str) -> usize {
+    let disk = Disk::from_blocks(&parse_blocks(input));
+
+    checksum(&compact_blocks(&disk).segments)
 }
 
 fn part2(input: &str) -> usize {
-    let mut blocks = parse_blocks(input);
-
-    for i in (0..blocks.len()).rev() {
-        if let Block::File { length, .. } = &blocks[i] {
-            let file_length = *length;
-            let (empty_slot, empty_slot_length) =
-                match blocks.iter().enumerate().find_map(|(i, block)| {
-                    if let Block::Empty { length } = block {
-                        if *length >= file_length {
-                            Some((i, length))
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    }
-                }) {
-                    Some(x) => x,
-                    None => continue,
-                };
-            if empty_slot > i {
-                continue;
+    let disk = Disk::from_blocks(&parse_blocks(input));
+
+    checksum(&compact_files(&disk).segments)
+}
+
+fn checksum(segments: &[Segment]) -> usize {
+    segments
+        .iter()
+        .map(|segment| {
+            // Sum of `position..position + length`, times the file id
+            let positions = segment.position * segment.length
+                + segment.length * segment.length.saturating_sub(1) / 2;
+            segment.id * positions
+        })
+        .sum()
+}
+
+// Inverse of `parse_blocks`. File ids are implied by their order, so they're
+// lost for any layout where files aren't in id order, like a compacted one. A
+// zero-length block is written wherever two blocks of the same kind meet, and
+// blocks longer than 9 are split up that way too.
+fn to_dense_map(blocks: &[Block]) -> String {
+    let mut map = String::with_capacity(blocks.len());
+    let mut expect_file = true;
+
+    for block in blocks {
+        let is_file = matches!(block, Block::File { .. });
+        let mut remaining = block.length();
+        loop {
+            if is_file != expect_file {
+                map.push('0');
             }
+            let length = remaining.min(MAX_SPAN_LENGTH);
+            map.push(char::from_digit(length as u32, 10).unwrap());
+            expect_file = !is_file;
 
-            let empty_left = *empty_slot_length - file_length;
-            if empty_left == 0 {
-                blocks.swap(i, empty_slot);
-            } else {
-                let file = std::mem::replace(
-                    &mut blocks[i],
-                    Block::Empty {
-                        length: file_length,
-                    },
-                );
-                blocks.insert(empty_slot, file);
-                blocks[empty_slot + 1] = Block::Empty { length: empty_left };
+            remaining -= length;
+            if remaining == 0 {
+                break;
             }
         }
     }
 
-    checksum(blocks_to_flat_list(blocks))
+    map
 }
 
-fn checksum(blocks: Vec<Option<usize>>) -> usize {
+fn blocks_from_segments(segments: &[Segment]) -> Vec<Block> {
+    let mut segments = segments.to_vec();
+    segments.sort_by_key(|segment| segment.position);
+
+    let mut blocks = Vec::with_capacity(segments.len() * 2);
+    let mut position = 0;
+    for segment in segments {
+        if segment.position > position {
+            blocks.push(Block::Empty {
+                length: segment.position - position,
+            });
+        }
+        blocks.push(Block::File {
+            id: segment.id,
+            length: segment.length,
+        });
+        position = segment.position + segment.length;
+    }
+
+    blocks
+}
+
+fn layout(blocks: &[Block]) -> Vec<Option<usize>> {
     blocks
         .iter()
-        .enumerate()
-        .map(|(i, x)| x.map(|x| i * x).unwrap_or(0))
-        .sum()
+        .flat_map(|block| match *block {
+            Block::File { id, length } => vec![Some(id); length],
+            Block::Empty { length } => vec![None; length],
+        })
+        .collect()
+}
+
+// Files with ids above 9 don't fit in a single character, so they're written
+// as `[id]` instead.
+fn render_layout(layout: &[Option<usize>]) -> String {
+    layout
+        .iter()
+        .map(|block| match block {
+            Some(id) if *id < 10 => id.to_string(),
+            Some(id) => format!("[{}]", id),
+            None => ".".to_string(),
+        })
+        .collect()
+}
+
+fn render_blocks(blocks: &[Block]) -> String {
+    render_layout(&layout(blocks))
+}
+
+// The layout before any moves, followed by the layout after each move.
+fn trace(blocks: &[Block], moves: &[Move]) -> Vec<String> {
+    let mut layout = layout(blocks);
+    let mut lines = vec![render_layout(&layout)];
+
+    for m in moves {
+        layout[m.from..m.from + m.length].fill(None);
+        layout[m.to..m.to + m.length].fill(Some(m.id));
+        lines.push(render_layout(&layout));
+    }
+
+    lines
 }
 
 #[cfg(test)]
@@ -140,4 +602,170 @@ mod tests {
     fn test_day9_part2() {
         assert_eq!(part2(TEST_INPUT), 2858);
     }
+
+    #[test]
+    fn test_day9_large_input() {
+        // Long enough that the old quadratic scans would take minutes
+        let input: String = (0..200_000)
+            .map(|i| char::from_digit((i * 7 + i / 3) % 10, 10).unwrap())
+            .collect();
+        let disk = Disk::from_blocks(&parse_blocks(&input));
+
+        for compaction in [compact_blocks(&disk), compact_files(&disk)] {
+            let mut lengths = vec![0; disk.files.len()];
+            for segment in &compaction.segments {
+                lengths[segment.id] += segment.length;
+            }
+            assert!(disk
+                .files
+                .iter()
+                .all(|file| lengths[file.id] == file.length));
+        }
+    }
+
+    #[test]
+    fn test_day9_round_trip() {
+        let blocks = parse_blocks(TEST_INPUT);
+        assert_eq!(to_dense_map(&blocks), TEST_INPUT);
+        assert_eq!(
+            render_blocks(&blocks),
+            "00...111...2...333.44.5555.6666.777.888899"
+        );
+
+        let compacted = compact_files(&Disk::from_blocks(&blocks));
+        assert_eq!(
+            render_blocks(&blocks_from_segments(&compacted.segments)),
+            "00992111777.44.333....5555.6666.....8888"
+        );
+
+        // Moving files out leaves gaps longer than a single digit can hold
+        let blocks = parse_blocks("1055559");
+        let compacted = blocks_from_segments(&compact_files(&Disk::from_blocks(&blocks)).segments);
+        assert_eq!(render_blocks(&compacted), "01111122222..........333333333");
+        let dense = to_dense_map(&compacted);
+        assert_eq!(dense, "105059019");
+        assert_eq!(
+            render_blocks(&parse_blocks(&dense)),
+            "01111122222..........444444444"
+        );
+    }
+
+    #[test]
+    fn test_day9_trace() {
+        let blocks = parse_blocks("12345");
+        let moves: Vec<Move> = compact_blocks(&Disk::from_blocks(&blocks))
+            .moves
+            .into_iter()
+            .flat_map(Move::blocks)
+            .collect();
+        assert_eq!(
+            trace(&blocks, &moves),
+            [
+                "0..111....22222",
+                "02.111....2222.",
+                "022111....222..",
+                "0221112...22...",
+                "02211122..2....",
+                "022111222......",
+            ]
+        );
+
+        let blocks = parse_blocks(TEST_INPUT);
+        assert_eq!(
+            trace(&blocks, &compact_files(&Disk::from_blocks(&blocks)).moves),
+            [
+                "00...111...2...333.44.5555.6666.777.888899",
+                "0099.111...2...333.44.5555.6666.777.8888..",
+                "0099.1117772...333.44.5555.6666.....8888..",
+                "0099.111777244.333....5555.6666.....8888..",
+                "00992111777.44.333....5555.6666.....8888..",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_day9_strategies() {
+        let disk = Disk::from_blocks(&parse_blocks(TEST_INPUT));
+        let reports: Vec<Report> = STRATEGIES
+            .iter()
+            .map(|strategy| Report::new(&strategy.compact(&disk)))
+            .collect();
+
+        assert_eq!(reports[0].checksum, 1928);
+        assert_eq!(reports[1].checksum, 2858);
+        assert_eq!(
+            reports[4],
+            Report {
+                checksum: 2453,
+                moves: 9,
+                free_gaps: 0,
+                fragmented_files: 0,
+            }
+        );
+        for report in &reports[1..] {
+            assert_eq!(report.fragmented_files, 0);
+        }
+    }
+
+    // Reads the gaps and fragments straight off the blocks
+    fn report_by_brute_force(segments: &[Segment]) -> (usize, usize) {
+        let end = segments
+            .iter()
+            .map(|segment| segment.position + segment.length)
+            .max()
+            .unwrap_or(0);
+        let mut layout = vec![None; end];
+        for segment in segments {
+            layout[segment.position..segment.position + segment.length].fill(Some(segment.id));
+        }
+
+        let used: Vec<usize> = (0..end).filter(|&i| layout[i].is_some()).collect();
+        let free_gaps = used.windows(2).filter(|pair| pair[1] > pair[0] + 1).count();
+
+        let mut cells: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &i in &used {
+            cells.entry(layout[i].unwrap()).or_default().push(i);
+        }
+        let fragmented_files = cells
+            .values()
+            .filter(|cells| cells.windows(2).any(|pair| pair[1] != pair[0] + 1))
+            .count();
+
+        (free_gaps, fragmented_files)
+    }
+
+    #[test]
+    fn test_day9_reports_match_brute_force() {
+        let disk = Disk::from_blocks(&parse_blocks("42069"));
+        let report = Report::new(&BlockFill.compact(&disk));
+        assert_eq!((report.free_gaps, report.fragmented_files), (0, 0));
+
+        // An empty file stays where it is
+        let disk = Disk::from_blocks(&parse_blocks("1901"));
+        assert_eq!(FirstFit.compact(&disk).moves.len(), 0);
+
+        let mut seed = 9usize;
+        for _ in 0..500 {
+            let input: String = (0..seed % 13 + 1)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    char::from_digit((seed >> 33) as u32 % 10, 10).unwrap()
+                })
+                .collect();
+            let disk = Disk::from_blocks(&parse_blocks(&input));
+
+            for strategy in STRATEGIES {
+                let compaction = strategy.compact(&disk);
+                let report = Report::new(&compaction);
+                assert_eq!(
+                    (report.free_gaps, report.fragmented_files),
+                    report_by_brute_force(&compaction.segments),
+                    "{} on {}",
+                    strategy.name(),
+                    input
+                );
+                assert!(compaction.moves.iter().all(|m| m.length > 0));
+            }
+        }
+    }
 }